@@ -1,16 +1,33 @@
 extern crate mm_client;
+extern crate reqwest;
 
+use self::reqwest::blocking::ClientBuilder;
 use mm_client::Client;
 use mm_client::MMCError;
 
 use client::client::APIClient;
 use client::error::{ClientError, ClientResult};
-use config::APIConfig;
+use config::{APIConfig, ProxyConfig};
+use hooks::proxy_for;
 
 pub struct MMClient {
     client: Client,
 }
 
+impl MMClient {
+    // Sends every request to the API through the proxy
+    pub fn with_proxy(self, proxy: &ProxyConfig) -> ClientResult<MMClient> {
+        let network_client = ClientBuilder::new()
+            .proxy(proxy_for(proxy).map_err(|_| ClientError::ConfigError)?)
+            .build()
+            .map_err(|_| ClientError::InitializationError)?;
+
+        Ok(MMClient {
+            client: self.client.with_network_client(network_client),
+        })
+    }
+}
+
 impl APIClient for MMClient {
     fn new(config: Option<&APIConfig>) -> ClientResult<MMClient> {
         config
@@ -103,3 +120,58 @@ impl APIClient for MMClient {
             .map_err(ClientError::API)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use client::{APIClient, MMClient};
+    use config::{APIConfig, ProxyConfig};
+
+    #[test]
+    fn sends_requests_through_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_url = format!("http://{}/", listener.local_addr().unwrap());
+
+        // Records the head of the first request that the proxy receives and then hangs up
+        let proxy = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+
+            BufReader::new(stream)
+                .lines()
+                .map(|line| line.unwrap())
+                .take_while(|line| !line.is_empty())
+                .collect::<Vec<String>>()
+        });
+
+        let config = APIConfig {
+            key: "key".to_string(),
+            secret: "secret".to_string(),
+            env: None,
+            changelog_max_timespan: 0,
+        };
+
+        let client = MMClient::new(Some(&config))
+            .and_then(|client| {
+                client.with_proxy(&ProxyConfig {
+                    url: proxy_url,
+                    username: Some("user".to_string()),
+                    password: Some("pass".to_string()),
+                    no_proxy: vec![],
+                })
+            })
+            .unwrap();
+
+        assert!(client.changes("2017-01-01T00:00:00.000Z").is_err());
+
+        let head = proxy.join().unwrap();
+
+        assert_eq!(head[0], "CONNECT media.services.pbs.org:443 HTTP/1.1");
+        assert!(
+            head.iter()
+                .any(|line| line.to_lowercase() == "proxy-authorization: basic dxnlcjpwyxnz")
+        );
+    }
+}
@@ -11,6 +11,7 @@ use config::APIConfig;
 #[derive(Clone, Debug)]
 pub struct TestClient {
     response: Option<String>,
+    authorized: bool,
     pub reqs: Arc<Mutex<Vec<String>>>,
 }
 
@@ -19,6 +20,11 @@ impl TestClient {
         self.response = Some(response)
     }
 
+    // Rejects every request as if the API key had lost access to everything
+    pub fn revoke_access(&mut self) {
+        self.authorized = false
+    }
+
     pub fn get_reqs(&self) -> Vec<String> {
         self.reqs.lock().unwrap().clone()
     }
@@ -28,6 +34,7 @@ impl APIClient for TestClient {
     fn new(_: Option<&APIConfig>) -> ClientResult<TestClient> {
         Ok(TestClient {
             response: None,
+            authorized: true,
             reqs: Arc::new(Mutex::new(vec![])),
         })
     }
@@ -35,6 +42,11 @@ impl APIClient for TestClient {
     fn url(&self, url: &str) -> ClientResult<String> {
         let mut reqs = self.reqs.lock().unwrap();
         reqs.push(url.to_string());
+
+        if !self.authorized {
+            return Err(ClientError::API(MMCError::NotAuthorized));
+        }
+
         self.response
             .clone()
             .ok_or(ClientError::API(MMCError::ResourceNotFound))
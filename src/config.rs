@@ -1,24 +1,67 @@
 extern crate log;
+extern crate reqwest;
+extern crate serde;
+extern crate serde_json;
 extern crate toml;
 
+use self::reqwest::Url;
+use self::serde::de::value::StrDeserializer;
+use self::serde::de::{self, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess,
+                      Visitor};
+use self::serde::Deserialize;
+use self::serde_json::Value as Json;
+
+use std::cell::Cell;
+use std::cmp;
 use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::Read;
 
+use objects::OBJECT_TYPES;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Config {
+    #[serde(default)]
     pub db: DBConfig,
     pub mm: APIConfig,
+    #[serde(default)]
     pub thread_pool_size: usize,
+    #[serde(default = "default_min_runtime_delta")]
     pub min_runtime_delta: i64,
+    #[serde(default = "default_lookback_timeframe")]
     pub lookback_timeframe: i64,
+    #[serde(default)]
     pub ignore_skip: bool,
+    #[serde(default)]
+    pub volatile_fields: Vec<String>,
+    #[serde(default)]
     pub log: LogConfig,
+    #[serde(default)]
     pub enable_hooks: bool,
     pub hooks: Option<HookConfig>,
+    #[serde(default)]
+    pub cascade_deletes: Vec<String>,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub delivery: DeliveryConfig,
+    #[serde(default)]
+    pub delete_guard: DeleteGuardConfig,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+fn default_min_runtime_delta() -> i64 {
+    30
+}
+
+fn default_lookback_timeframe() -> i64 {
+    60
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct LogConfig {
     pub location: Option<String>,
     pub level: Option<String>,
@@ -27,11 +70,55 @@ pub struct LogConfig {
 // Database configuration/
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DBConfig {
+    #[serde(default)]
+    pub engine: DBEngine,
+    #[serde(default = "default_db_host")]
     pub host: String,
+    #[serde(default = "default_db_port")]
     pub port: u16,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
     pub password: String,
+    pub path: Option<String>,
+}
+
+impl Default for DBConfig {
+    fn default() -> DBConfig {
+        DBConfig {
+            engine: DBEngine::default(),
+            host: default_db_host(),
+            port: default_db_port(),
+            name: String::new(),
+            username: String::new(),
+            password: String::new(),
+            path: None,
+        }
+    }
+}
+
+fn default_db_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_db_port() -> u16 {
+    27017
+}
+
+// Storage engines that can back the cache
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DBEngine {
+    Mongo,
+    Sqlite,
+}
+
+impl Default for DBEngine {
+    fn default() -> DBEngine {
+        DBEngine::Mongo
+    }
 }
 
 // MediaManagerAPI configuration
@@ -40,32 +127,1382 @@ pub struct APIConfig {
     pub key: String,
     pub secret: String,
     pub env: Option<String>,
+    #[serde(default = "default_changelog_max_timespan")]
     pub changelog_max_timespan: i64,
 }
 
+// 29 days, as in config.toml.example
+fn default_changelog_max_timespan() -> i64 {
+    2505600
+}
+
 // API Webhook configuration
-pub type HookConfig = BTreeMap<String, Vec<BTreeMap<String, String>>>;
+pub type HookConfig = BTreeMap<String, Vec<Hook>>;
 
-pub fn parse_config(path: &str) -> Option<Config> {
-    let mut config_toml = String::new();
+// Settings for the HTTP client that is shared by all hooks. Durations are in milliseconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub max_idle_connections: usize,
+    pub idle_timeout: u64,
+    pub connect_timeout: u64,
+    pub timeout: u64,
+    pub proxy: Option<ProxyConfig>,
+    pub circuit_breaker: BreakerConfig,
+}
+
+impl Default for HttpConfig {
+    fn default() -> HttpConfig {
+        HttpConfig {
+            max_idle_connections: 8,
+            // Shorter than the 5 second keep alive of common servers, so that a connection is not
+            // reused just as the hook closes it
+            idle_timeout: 4000,
+            connect_timeout: 10000,
+            timeout: 30000,
+            proxy: None,
+            circuit_breaker: BreakerConfig::default(),
+        }
+    }
+}
+
+// Consecutive failed deliveries after which deliveries to a hook are deferred, and the number of
+// milliseconds to wait before trying it again. A limit of 0 never defers deliveries
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct BreakerConfig {
+    pub failures: u32,
+    pub cooldown: u64,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> BreakerConfig {
+        BreakerConfig {
+            failures: 5,
+            cooldown: 30000,
+        }
+    }
+}
+
+// Settings for the pool of workers that deliver notifications to hooks. Concurrency and the
+// rate limit apply to each hook target separately and can be overridden by a hook
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct DeliveryConfig {
+    pub workers: usize,
+    pub concurrency: usize,
+    pub rate_limit: Option<f64>,
+    pub queue_size: usize,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> DeliveryConfig {
+        DeliveryConfig {
+            workers: 8,
+            concurrency: 4,
+            rate_limit: None,
+            queue_size: 1000,
+        }
+    }
+}
+
+// Limits on the deletes that a single run may make because the API stopped returning objects.
+// Deletes beyond them are held in a quarantine for review. The guard is off unless a limit is set
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct DeleteGuardConfig {
+    pub max_deletes: Option<usize>,
+    pub max_fraction: Option<f64>,
+    pub min_deletes: usize,
+}
+
+impl Default for DeleteGuardConfig {
+    fn default() -> DeleteGuardConfig {
+        DeleteGuardConfig {
+            max_deletes: None,
+            max_fraction: None,
+            min_deletes: 10,
+        }
+    }
+}
+
+impl DeleteGuardConfig {
+    pub fn enabled(&self) -> bool {
+        self.max_deletes.is_some() || self.max_fraction.is_some()
+    }
+
+    // Whether deleting this many of the references processed in a run goes over the limits. The
+    // fraction is only checked once there are enough deletes for it to mean something
+    pub fn exceeded(&self, deletes: usize, refs: usize) -> bool {
+        let over_count = self.max_deletes.map_or(false, |max| deletes > max);
+        let over_fraction = self.max_fraction.map_or(false, |max| {
+            deletes >= self.min_deletes && deletes as f64 > max * refs as f64
+        });
+
+        over_count || over_fraction
+    }
+}
+
+// Outbound proxy used for requests to hooks and to the Media Manager API
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProxyConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+// A single webhook that notifications for a type are sent to
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Hook {
+    #[serde(default)]
+    pub kind: HookKind,
+    pub url: Option<String>,
+    pub path: Option<String>,
+    #[serde(default)]
+    pub rotate: RotationConfig,
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub timeout: Option<u64>,
+    pub concurrency: Option<usize>,
+    pub rate_limit: Option<f64>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub include_changes: bool,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub filter: HookFilter,
+    #[serde(default)]
+    pub projection: Projection,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub format: PayloadFormat,
+    #[serde(default)]
+    pub cloudevents: CloudEventsConfig,
+    #[serde(default)]
+    pub methods: HttpMethods,
+}
+
+impl Hook {
+    // The url, path or command that identifies where the hook delivers to
+    pub fn target(&self) -> Option<&String> {
+        match self.kind {
+            HookKind::Http => self.url.as_ref(),
+            HookKind::File => self.path.as_ref(),
+            HookKind::Exec => self.command.as_ref(),
+        }
+    }
+}
+
+// Destinations that a hook can deliver notifications to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookKind {
+    Http,
+    File,
+    Exec,
+}
+
+impl Default for HookKind {
+    fn default() -> HookKind {
+        HookKind::Http
+    }
+}
+
+// The HTTP methods that an http hook is sent each kind of notification with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HttpMethods {
+    pub create: String,
+    pub update: String,
+    pub delete: String,
+}
+
+impl Default for HttpMethods {
+    fn default() -> HttpMethods {
+        HttpMethods {
+            create: "POST".to_string(),
+            update: "POST".to_string(),
+            delete: "DELETE".to_string(),
+        }
+    }
+}
+
+// Limits on the size in bytes and age in seconds of a file hook before it is rotated
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct RotationConfig {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<u64>,
+}
+
+// Limits the notifications that a hook receives. Every rule that is set must match
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct HookFilter {
+    pub franchises: Vec<String>,
+    pub shows: Vec<String>,
+    pub attributes: BTreeMap<String, Json>,
+    pub exclude_ids: Vec<String>,
+}
+
+// Shapes the body that a hook receives. Paths are dotted and relative to the payload data
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Projection {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub rename: BTreeMap<String, String>,
+    pub max_parent_depth: Option<usize>,
+}
+
+// Shapes of the body that a hook receives
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    Default,
+    CloudEvents,
+}
+
+impl Default for PayloadFormat {
+    fn default() -> PayloadFormat {
+        PayloadFormat::Default
+    }
+}
+
+// Whether CloudEvents attributes are sent in the body alongside the data or as http headers
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CloudEventsMode {
+    Structured,
+    Binary,
+}
 
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(_) => {
-            error!(
-                "Could not find config file at {}. See the included README and \
+impl Default for CloudEventsMode {
+    fn default() -> CloudEventsMode {
+        CloudEventsMode::Structured
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct CloudEventsConfig {
+    pub mode: CloudEventsMode,
+    pub source: String,
+}
+
+impl Default for CloudEventsConfig {
+    fn default() -> CloudEventsConfig {
+        CloudEventsConfig {
+            mode: CloudEventsMode::default(),
+            source: "/mm_api_notify".to_string(),
+        }
+    }
+}
+
+// TLS settings for requests to a hook. Certificates are verified strictly unless a hook opts out
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub ca_bundle: Option<String>,
+    pub client_identity: Option<String>,
+    pub client_identity_password: Option<String>,
+    pub client_certificate: Option<String>,
+    pub client_key: Option<String>,
+    pub accept_invalid_hostnames: bool,
+}
+
+// Policy for retrying failed deliveries to a hook. Delays are in milliseconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub base_delay: u64,
+    pub max_delay: u64,
+    pub jitter: bool,
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 1,
+            base_delay: 500,
+            max_delay: 30000,
+            jitter: true,
+            retry_statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+impl Config {
+    // A config with empty credentials and every option off, for tests to fill in
+    #[cfg(test)]
+    pub fn empty() -> Config {
+        let empty = "".to_string();
+
+        Config {
+            db: DBConfig {
+                engine: DBEngine::Mongo,
+                host: empty.clone(),
+                port: 0,
+                name: empty.clone(),
+                username: empty.clone(),
+                password: empty.clone(),
+                path: None,
+            },
+            mm: APIConfig {
+                key: empty.clone(),
+                secret: empty,
+                env: None,
+                changelog_max_timespan: 0,
+            },
+            thread_pool_size: 0,
+            min_runtime_delta: 0,
+            lookback_timeframe: 0,
+            ignore_skip: false,
+            log: LogConfig {
+                location: None,
+                level: None,
+            },
+            volatile_fields: vec![],
+            enable_hooks: false,
+            hooks: None,
+            cascade_deletes: vec![],
+            http: HttpConfig::default(),
+            delivery: DeliveryConfig::default(),
+            delete_guard: DeleteGuardConfig::default(),
+        }
+    }
+
+    // Constraints on the values of the config that its types cannot express
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+
+        if self.mm.key.trim().is_empty() {
+            errors.push("mm.key must not be empty".to_string());
+        }
+
+        if self.mm.secret.trim().is_empty() {
+            errors.push("mm.secret must not be empty".to_string());
+        }
+
+        if self.min_runtime_delta <= 0 {
+            errors.push("min_runtime_delta must be positive".to_string());
+        }
+
+        if self.lookback_timeframe >= self.mm.changelog_max_timespan {
+            errors.push(format!(
+                "lookback_timeframe ({}) must be smaller than mm.changelog_max_timespan ({})",
+                self.lookback_timeframe, self.mm.changelog_max_timespan
+            ));
+        }
+
+        for object_type in &self.cascade_deletes {
+            if !OBJECT_TYPES.contains(&object_type.as_str()) {
+                errors.push(format!(
+                    "cascade_deletes lists `{}`, which is not one of {}",
+                    object_type,
+                    OBJECT_TYPES.join(", ")
+                ));
+            }
+        }
+
+        // Hooks are only checked once they are enabled, so unfinished ones do not stop the service
+        if let Some(hooks) = self.hooks.as_ref().filter(|_| self.enable_hooks) {
+            // Hooks that deliver to the same target share its limits, so they must agree on them
+            let mut concurrency_limits: BTreeMap<&String, (String, usize)> = BTreeMap::new();
+            let mut rate_limits: BTreeMap<&String, (String, f64)> = BTreeMap::new();
+
+            for (object_type, hooks) in hooks {
+                if !OBJECT_TYPES.contains(&object_type.as_str()) {
+                    errors.push(format!(
+                        "hooks.{} is not one of {}",
+                        object_type,
+                        OBJECT_TYPES.join(", ")
+                    ));
+                }
+
+                for (i, hook) in hooks.iter().enumerate() {
+                    let key = format!("hooks.{}[{}]", object_type, i);
+
+                    if hook.target().map_or(true, |target| target.trim().is_empty()) {
+                        let field = match hook.kind {
+                            HookKind::Http => "url",
+                            HookKind::File => "path",
+                            HookKind::Exec => "command",
+                        };
+
+                        errors.push(format!("{} is missing its {}", key, field));
+                    }
+
+                    if let Some(url) = hook.url.as_ref().filter(|url| !url.is_empty()) {
+                        if let Err(err) = Url::parse(url) {
+                            errors.push(format!(
+                                "{}.url `{}` is not a valid url: {}",
+                                key, url, err
+                            ));
+                        }
+                    }
+
+                    let tls = &hook.tls;
+
+                    if tls.client_certificate.is_some() != tls.client_key.is_some() {
+                        errors.push(format!(
+                            "{}.tls needs both a client_certificate and a client_key",
+                            key
+                        ));
+                    }
+
+                    if tls.client_identity.is_some() && tls.client_certificate.is_some() {
+                        errors.push(format!(
+                            "{}.tls has both a client_identity and a client_certificate",
+                            key
+                        ));
+                    }
+
+                    if let Some(target) = hook.target() {
+                        if let Some(concurrency) = hook.concurrency {
+                            match concurrency_limits.get(target) {
+                                Some(&(ref first, limit)) if limit != concurrency => {
+                                    errors.push(format!(
+                                        "{}.concurrency ({}) differs from {}.concurrency ({}) for \
+                                         the same target",
+                                        key, concurrency, first, limit
+                                    ))
+                                }
+                                Some(_) => (),
+                                None => {
+                                    concurrency_limits.insert(target, (key.clone(), concurrency));
+                                }
+                            }
+                        }
+
+                        if let Some(rate_limit) = hook.rate_limit {
+                            match rate_limits.get(target) {
+                                Some(&(ref first, limit)) if limit != rate_limit => {
+                                    errors.push(format!(
+                                        "{}.rate_limit ({}) differs from {}.rate_limit ({}) for \
+                                         the same target",
+                                        key, rate_limit, first, limit
+                                    ))
+                                }
+                                Some(_) => (),
+                                None => {
+                                    rate_limits.insert(target, (key.clone(), rate_limit));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+// Environment variables that start with this override the field that the rest of their name
+// leads to, such as MM_API_NOTIFY_MM__SECRET for the secret of the [mm] table
+const ENV_PREFIX: &str = "MM_API_NOTIFY_";
+
+// Values of string fields with these prefixes are replaced by the contents of a file or an env
+// variable
+const FILE_REFERENCE: &str = "file:";
+const ENV_REFERENCE: &str = "env:";
+
+// Why a config file could not be loaded. Parse errors carry the line and column they occurred at
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String, io::Error),
+    Parse(toml::de::Error),
+    Override(String, String),
+    Reference(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Read(ref path, ref err) => write!(
+                f,
+                "Could not read the config file at {}: {}. See the included README and \
                  config.toml.example for configuration instructions.",
-                path
-            );
-            return None;
+                path, err
+            ),
+            ConfigError::Parse(ref err) => write!(f, "Invalid config: {}", err),
+            ConfigError::Override(ref name, ref problem) => {
+                write!(f, "Invalid override {}: {}", name, problem)
+            }
+            ConfigError::Reference(ref key, ref problem) => {
+                write!(f, "Could not resolve `{}`: {}", key, problem)
+            }
         }
-    };
+    }
+}
 
-    file.read_to_string(&mut config_toml)
-        .or_else(|err| {
-            error!("Failure while reading config: [{}]", err);
-            Err(err)
-        })
+// Problems found in a config file. Any error prevents the service from starting with it
+#[derive(Debug, Default, PartialEq)]
+pub struct Validation {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+fn read_config(path: &str) -> Result<String, ConfigError> {
+    let mut config_toml = String::new();
+
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut config_toml))
+        .map(|_| config_toml)
+        .map_err(|err| ConfigError::Read(path.to_string(), err))
+}
+
+pub fn parse_config(path: &str) -> Result<Config, ConfigError> {
+    read_config(path)
+        .and_then(|config_toml| load_config(&config_toml, env::vars()))
+        .map(|(_, config)| config)
+}
+
+pub fn validate_config(path: &str) -> Validation {
+    match read_config(path).and_then(|config_toml| load_config(&config_toml, env::vars())) {
+        Ok((layered, config)) => {
+            let mut warnings = vec![];
+
+            if let (Ok(raw), Ok(known)) =
+                (serde_json::to_value(layered), serde_json::to_value(&config))
+            {
+                unknown_keys(&raw, &known, "", &mut warnings);
+            }
+
+            Validation {
+                errors: config.validate(),
+                warnings: warnings
+                    .into_iter()
+                    .map(|key| format!("unknown key `{}` is ignored", key))
+                    .collect(),
+            }
+        }
+        Err(err) => Validation {
+            errors: vec![err.to_string()],
+            warnings: vec![],
+        },
+    }
+}
+
+// Layers the environment over the config file before reading it into a config. Returns the
+// layered values along with the config so that keys which were not read can be found.
+fn load_config<I>(config_toml: &str, vars: I) -> Result<(toml::Value, Config), ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let vars: BTreeMap<String, String> = vars.into_iter().collect();
+    let mut layered = toml::from_str::<toml::Value>(config_toml).map_err(ConfigError::Parse)?;
+    let mut added = false;
+
+    for (name, value) in vars.iter().filter(|(name, _)| name.starts_with(ENV_PREFIX)) {
+        added |= apply_override(&mut layered, name, value)?;
+    }
+
+    resolve_references(&mut layered, "", &vars)?;
+
+    match layered.clone().try_into::<Config>() {
+        Ok(config) => Ok((layered, config)),
+        Err(err) => Err(file_error(config_toml, err, added)),
+    }
+}
+
+// Layered values no longer know where they were in the file. Overrides are checked against the
+// type of their field as they are applied, so when they only replace values that the file sets
+// and the file on its own fails, the error lies in the file and is reported with its position.
+fn file_error(config_toml: &str, err: toml::de::Error, added: bool) -> ConfigError {
+    match toml::from_str::<Config>(config_toml) {
+        Err(file_err) if !added && file_err.line_col().is_some() => ConfigError::Parse(file_err),
+        _ => ConfigError::Parse(err),
+    }
+}
+
+fn key_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// The rest of the variable name is split on double underscores into the keys of nested tables,
+// or the indexes of arrays that the file already defines, such as HOOKS__ASSET__0__PASSWORD.
+// Returns whether the override added a field that the file does not set.
+fn apply_override(config: &mut toml::Value, name: &str, raw: &str) -> Result<bool, ConfigError> {
+    let invalid = |problem: String| ConfigError::Override(name.to_string(), problem);
+
+    let keys: Vec<String> = name[ENV_PREFIX.len()..]
+        .split("__")
+        .map(|key| key.to_lowercase())
+        .collect();
+
+    let field_type = FieldType::of(&keys);
+    let mut current = config;
+
+    for (depth, key) in keys.iter().enumerate() {
+        let last = depth == keys.len() - 1;
+
+        current = match current {
+            toml::Value::Table(table) => {
+                if last {
+                    let value = override_value(raw, field_type, table.get(key)).map_err(invalid)?;
+                    return Ok(table.insert(key.clone(), value).is_none());
+                }
+
+                table
+                    .entry(key.clone())
+                    .or_insert_with(|| toml::Value::Table(toml::value::Table::new()))
+            }
+            toml::Value::Array(array) => {
+                let entry = key.parse::<usize>()
+                    .ok()
+                    .and_then(move |index| array.get_mut(index))
+                    .ok_or_else(|| {
+                        invalid(format!(
+                            "`{}` is not an index of `{}`",
+                            key,
+                            keys[..depth].join(".")
+                        ))
+                    })?;
+
+                if last {
+                    *entry = override_value(raw, field_type, Some(entry)).map_err(invalid)?;
+                    return Ok(false);
+                }
+
+                entry
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "`{}` does not have fields",
+                    keys[..depth].join(".")
+                )))
+            }
+        };
+    }
+
+    Ok(false)
+}
+
+// Variables are always strings. They are read as the type of the field that they override so that
+// numbers, booleans and arrays can be given. Fields that take any value, such as the attributes of
+// a filter, are read as a TOML value unless they already hold a string, falling back to a string.
+fn override_value(
+    raw: &str,
+    field_type: Option<FieldType>,
+    current: Option<&toml::Value>,
+) -> Result<toml::Value, String> {
+    let parsed = toml::from_str::<toml::Value>(format!("value = {}", raw).as_str())
         .ok()
-        .and_then(|_| toml::from_str(&config_toml).ok())
+        .and_then(|mut parsed| parsed.as_table_mut().and_then(|table| table.remove("value")));
+
+    match field_type {
+        Some(FieldType::String) => Ok(toml::Value::String(raw.to_string())),
+        Some(FieldType::Variant(variants)) => if variants.contains(&raw) {
+            Ok(toml::Value::String(raw.to_string()))
+        } else {
+            Err(format!("`{}` is not one of {}", raw, variants.join(", ")))
+        },
+        Some(FieldType::Boolean) => raw.parse::<bool>()
+            .map(toml::Value::Boolean)
+            .map_err(|_| format!("`{}` is not true or false", raw)),
+        Some(FieldType::Integer(min, max)) => match raw.parse::<i64>() {
+            Ok(value) if value >= min && value <= max => Ok(toml::Value::Integer(value)),
+            Ok(_) => Err(format!("`{}` is not between {} and {}", raw, min, max)),
+            Err(_) => Err(format!("`{}` is not an integer", raw)),
+        },
+        Some(FieldType::Float) => raw.parse::<f64>()
+            .map(toml::Value::Float)
+            .map_err(|_| format!("`{}` is not a number", raw)),
+        Some(FieldType::Array) => match parsed {
+            Some(value @ toml::Value::Array(_)) => Ok(value),
+            _ => Err(format!("`{}` is not an array", raw)),
+        },
+        Some(FieldType::Table) => match parsed {
+            Some(value @ toml::Value::Table(_)) => Ok(value),
+            _ => Err(format!("`{}` is not a table", raw)),
+        },
+        None => match current {
+            Some(toml::Value::String(_)) => Ok(toml::Value::String(raw.to_string())),
+            _ => Ok(parsed.unwrap_or_else(|| toml::Value::String(raw.to_string()))),
+        },
+    }
+}
+
+// The type that a field of the config is read as
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldType {
+    String,
+    Variant(&'static [&'static str]),
+    Boolean,
+    Integer(i64, i64),
+    Float,
+    Array,
+    Table,
+}
+
+impl FieldType {
+    // The type of the field that the keys lead to, found by reading a config that only has those
+    // keys. There is none for fields that take any value or keys that are not fields.
+    fn of(keys: &[String]) -> Option<FieldType> {
+        let found = Cell::new(None);
+        let _ = Config::deserialize(Probe {
+            keys,
+            found: &found,
+        });
+
+        found.get()
+    }
+}
+
+// A deserializer that leads to a single field of the config and notes the type that the field asks
+// for. Reading stops with an error once it has been noted.
+struct Probe<'a> {
+    keys: &'a [String],
+    found: &'a Cell<Option<FieldType>>,
+}
+
+impl<'a> Probe<'a> {
+    fn found<T>(self, field_type: FieldType) -> Result<T, de::value::Error> {
+        if self.keys.is_empty() {
+            self.found.set(Some(field_type));
+        }
+
+        Err(de::Error::custom("the type of the field has been found"))
+    }
+
+    fn integer<T>(self, min: i64, max: u64) -> Result<T, de::value::Error> {
+        self.found(FieldType::Integer(min, cmp::min(max, i64::MAX as u64) as i64))
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for Probe<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("the field takes any value"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.found(FieldType::Boolean)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.integer(i8::MIN.into(), i8::MAX as u64)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.integer(i16::MIN.into(), i16::MAX as u64)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.integer(i32::MIN.into(), i32::MAX as u64)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.integer(i64::MIN, i64::MAX as u64)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.integer(0, u8::MAX.into())
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.integer(0, u16::MAX.into())
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.integer(0, u32::MAX.into())
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.integer(0, u64::MAX)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.found(FieldType::Float)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.found(FieldType::Float)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.found(FieldType::String)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.found(FieldType::String)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        self.found(FieldType::String)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Any index leads to an element of the same type
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.keys.split_first() {
+            None => self.found(FieldType::Array),
+            Some((index, keys)) if index.parse::<usize>().is_ok() => visitor.visit_seq(Path {
+                key: Some(index),
+                keys,
+                found: self.found,
+            }),
+            Some(_) => Err(de::Error::custom("the field is not a table")),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.keys.split_first() {
+            None => self.found(FieldType::Table),
+            Some((key, keys)) => visitor.visit_map(Path {
+                key: Some(key),
+                keys,
+                found: self.found,
+            }),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        variants: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.found(FieldType::Variant(variants))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("the key is not a field"))
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct tuple tuple_struct identifier
+    }
+}
+
+// The single key or element of a table or an array that leads on to the field
+struct Path<'a> {
+    key: Option<&'a String>,
+    keys: &'a [String],
+    found: &'a Cell<Option<FieldType>>,
+}
+
+impl<'de, 'a> MapAccess<'de> for Path<'a> {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.key.take() {
+            Some(key) => {
+                let key: StrDeserializer<Self::Error> = key.as_str().into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        seed.deserialize(Probe {
+            keys: self.keys,
+            found: self.found,
+        })
+    }
+}
+
+impl<'de, 'a> SeqAccess<'de> for Path<'a> {
+    type Error = de::value::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.key.take() {
+            Some(_) => seed.deserialize(Probe {
+                keys: self.keys,
+                found: self.found,
+            }).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn resolve_references(
+    value: &mut toml::Value,
+    path: &str,
+    vars: &BTreeMap<String, String>,
+) -> Result<(), ConfigError> {
+    let unresolved = |problem: String| ConfigError::Reference(path.to_string(), problem);
+
+    match value {
+        toml::Value::String(string) => {
+            let resolved = if let Some(file) = string.strip_prefix(FILE_REFERENCE) {
+                // Secret files usually end in a newline that is not part of the secret
+                fs::read_to_string(file)
+                    .map(|contents| contents.trim_end_matches(&['\r', '\n'][..]).to_string())
+                    .map_err(|err| unresolved(format!("could not read {}: {}", file, err)))?
+            } else if let Some(name) = string.strip_prefix(ENV_REFERENCE) {
+                vars.get(name)
+                    .cloned()
+                    .ok_or_else(|| unresolved(format!("{} is not set", name)))?
+            } else {
+                return Ok(());
+            };
+
+            *string = resolved;
+        }
+        toml::Value::Table(table) => for (key, value) in table.iter_mut() {
+            resolve_references(value, key_path(path, key).as_str(), vars)?;
+        },
+        toml::Value::Array(array) => for (index, value) in array.iter_mut().enumerate() {
+            resolve_references(value, format!("{}[{}]", path, index).as_str(), vars)?;
+        },
+        _ => (),
+    }
+
+    Ok(())
+}
+
+// Every field of the config is written out when it is serialized, so any key of the file that is
+// missing from the serialized config was not read into a field
+fn unknown_keys(raw: &Json, known: &Json, path: &str, unknown: &mut Vec<String>) {
+    match (raw, known) {
+        (Json::Object(raw), Json::Object(known)) => for (key, value) in raw {
+            let key_path = key_path(path, key);
+
+            match known.get(key) {
+                Some(known) => unknown_keys(value, known, key_path.as_str(), unknown),
+                None => unknown.push(key_path),
+            }
+        },
+        (Json::Array(raw), Json::Array(known)) => {
+            for (i, (raw, known)) in raw.iter().zip(known.iter()).enumerate() {
+                unknown_keys(raw, known, format!("{}[{}]", path, i).as_str(), unknown);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use config::{load_config, validate_config, Config, ConfigError, DBEngine, Validation};
+
+    const MINIMAL: &str = r#"
+thread_pool_size = 8
+min_runtime_delta = 30
+lookback_timeframe = 60
+enable_hooks = false
+ignore_skip = false
+
+[db]
+engine = "sqlite"
+path = "cache.db"
+
+[mm]
+key = "key"
+secret = "secret"
+changelog_max_timespan = 2505600
+
+[log]
+"#;
+
+    fn load(config_toml: &str, vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars = vars.iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()));
+
+        load_config(config_toml, vars).map(|(_, config)| config)
+    }
+
+    fn validate(name: &str, config_toml: &str) -> Validation {
+        let path = env::temp_dir().join(format!("mm_api_notify_{}.toml", name));
+        fs::write(&path, config_toml).unwrap();
+
+        let validation = validate_config(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        validation
+    }
+
+    #[test]
+    fn accepts_minimal_config() {
+        assert_eq!(validate("minimal", MINIMAL), Validation::default());
+    }
+
+    #[test]
+    fn does_not_cascade_deletes_by_default() {
+        assert!(load(MINIMAL, &[]).unwrap().cascade_deletes.is_empty());
+    }
+
+    #[test]
+    fn reports_placeholders_in_example_config() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml.example");
+        let validation = validate_config(path);
+
+        assert_eq!(
+            validation.errors,
+            vec![
+                "mm.key must not be empty",
+                "mm.secret must not be empty",
+            ]
+        );
+        assert!(validation.warnings.is_empty());
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let config_toml = format!(
+            "{}\n[hooks]\nasset = [\n  {{ url = \"https://example.org/\", timeout = \"5s\" }}\n]\n",
+            MINIMAL
+        );
+
+        let validation = validate("invalid_type", config_toml.as_str());
+
+        assert_eq!(validation.errors.len(), 1);
+        assert!(validation.errors[0].starts_with("Invalid config: invalid type: string \"5s\""));
+        assert!(validation.errors[0].ends_with("for key `hooks.asset` at line 21 column 45"));
+    }
+
+    #[test]
+    fn reports_semantic_problems() {
+        let config_toml = MINIMAL
+            .replace("min_runtime_delta = 30", "min_runtime_delta = 0")
+            .replace("lookback_timeframe = 60", "lookback_timeframe = 2505600")
+            .replace("key = \"key\"", "key = \"\"")
+            .replace("enable_hooks = false", "enable_hooks = true")
+            + "\n[hooks]\nshows = [{ url = \"https://example.org/\" }]\n\
+               asset = [{ url = \"example.org\" }, { kind = \"file\" }]\n";
+
+        let validation = validate("semantic", config_toml.as_str());
+
+        assert_eq!(
+            validation.errors,
+            vec![
+                "mm.key must not be empty",
+                "min_runtime_delta must be positive",
+                "lookback_timeframe (2505600) must be smaller than mm.changelog_max_timespan \
+                 (2505600)",
+                "hooks.asset[0].url `example.org` is not a valid url: relative URL without a base",
+                "hooks.asset[1] is missing its path",
+                "hooks.shows is not one of asset, episode, franchise, season, show, special",
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_problems_with_hooks_while_they_are_disabled() {
+        let config_toml = MINIMAL.to_string() + "\n[hooks]\nasset = [{ url = \"example.org\" }]\n";
+
+        assert_eq!(validate("disabled_hooks", config_toml.as_str()), Validation::default());
+    }
+
+    #[test]
+    fn reports_incomplete_client_certificates() {
+        let config_toml = MINIMAL.replace("enable_hooks = false", "enable_hooks = true")
+            + "\n[hooks]\nasset = [\n  \
+               { url = \"https://example.org/\", \
+               tls = { client_certificate = \"client.crt\" } },\n  \
+               { url = \"https://example.org/\", tls = { client_identity = \"client.p12\", \
+               client_certificate = \"client.crt\", client_key = \"client.key\" } }\n]\n";
+
+        let validation = validate("client_certificates", config_toml.as_str());
+
+        assert_eq!(
+            validation.errors,
+            vec![
+                "hooks.asset[0].tls needs both a client_certificate and a client_key",
+                "hooks.asset[1].tls has both a client_identity and a client_certificate",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_conflicting_limits_for_a_target() {
+        let config_toml = format!(
+            "{}\n[hooks]\n\
+             asset = [{{ url = \"https://example.org/\", concurrency = 1, rate_limit = 5.0 }}]\n\
+             show = [\n  {{ url = \"https://example.org/\", concurrency = 2 }},\n  \
+             {{ url = \"https://example.org/\", rate_limit = 5.0 }}\n]\n",
+            MINIMAL.replace("enable_hooks = false", "enable_hooks = true")
+        );
+
+        let validation = validate("conflicting_limits", config_toml.as_str());
+
+        assert_eq!(
+            validation.errors,
+            vec![
+                "hooks.show[0].concurrency (2) differs from hooks.asset[0].concurrency (1) for \
+                 the same target",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_unknown_keys() {
+        let config_toml = MINIMAL.replace("[db]", "thread_pool = 4\n\n[db]")
+            + "\n[delete_guard]\nmax_delete = 10\n\n[hooks]\n\
+               asset = [{ url = \"https://example.org/\", retries = 3 }]\n";
+
+        let validation = validate("unknown_keys", config_toml.as_str());
+
+        assert!(validation.errors.is_empty());
+        assert_eq!(
+            validation.warnings,
+            vec![
+                "unknown key `thread_pool` is ignored",
+                "unknown key `delete_guard.max_delete` is ignored",
+                "unknown key `hooks.asset[0].retries` is ignored",
+            ]
+        );
+    }
+
+    #[test]
+    fn overrides_fields_from_the_environment() {
+        let config_toml = MINIMAL.to_string()
+            + "\n[hooks]\nasset = [{ url = \"https://example.org/\", password = \"changeme\" }]\n";
+
+        let config = load(
+            config_toml.as_str(),
+            &[
+                ("MM_API_NOTIFY_MM__SECRET", "from-env"),
+                ("MM_API_NOTIFY_THREAD_POOL_SIZE", "4"),
+                ("MM_API_NOTIFY_HOOKS__ASSET__0__PASSWORD", "12345"),
+                ("MM_API_NOTIFY_LOG__LEVEL", "debug"),
+                ("MM_SECRET", "ignored"),
+            ],
+        ).unwrap();
+
+        assert_eq!(config.mm.secret, "from-env");
+        assert_eq!(config.thread_pool_size, 4);
+        assert_eq!(config.log.level, Some("debug".to_string()));
+
+        let hooks = config.hooks.unwrap();
+        assert_eq!(hooks["asset"][0].password, Some("12345".to_string()));
+    }
+
+    #[test]
+    fn keeps_overrides_of_unset_string_fields_as_strings() {
+        let config_toml = MINIMAL.replace("key = \"key\"\nsecret = \"secret\"\n", "")
+            + "\n[hooks]\nasset = [\n  { url = \"https://example.org/\" },\n  \
+               { url = \"https://example.com/\" }\n]\n";
+
+        let config = load(
+            config_toml.as_str(),
+            &[
+                ("MM_API_NOTIFY_MM__KEY", "12345"),
+                ("MM_API_NOTIFY_MM__SECRET", "true"),
+                ("MM_API_NOTIFY_DB__PASSWORD", "12345678"),
+                ("MM_API_NOTIFY_HOOKS__ASSET__0__SECRET", "1.5"),
+                ("MM_API_NOTIFY_HOOKS__ASSET__1__SECRET", "42"),
+                ("MM_API_NOTIFY_LOOKBACK_TIMEFRAME", "120"),
+            ],
+        ).unwrap();
+
+        assert_eq!(config.mm.key, "12345");
+        assert_eq!(config.mm.secret, "true");
+        assert_eq!(config.db.password, "12345678");
+        assert_eq!(config.lookback_timeframe, 120);
+
+        let hooks = config.hooks.unwrap();
+        assert_eq!(hooks["asset"][0].secret, Some("1.5".to_string()));
+        assert_eq!(hooks["asset"][1].secret, Some("42".to_string()));
+    }
+
+    #[test]
+    fn reads_overrides_as_the_type_of_their_field() {
+        let config_toml = MINIMAL.to_string()
+            + "\n[hooks]\nasset = [{ url = \"https://example.org/\" }]\n";
+
+        let config = load(
+            config_toml.as_str(),
+            &[
+                ("MM_API_NOTIFY_DB__ENGINE", "mongo"),
+                ("MM_API_NOTIFY_DB__PORT", "27018"),
+                ("MM_API_NOTIFY_HOOKS__ASSET__0__USERNAME", "12345"),
+                ("MM_API_NOTIFY_HOOKS__ASSET__0__RATE_LIMIT", "5"),
+                ("MM_API_NOTIFY_HOOKS__ASSET__0__RETRY__JITTER", "false"),
+                ("MM_API_NOTIFY_HOOKS__ASSET__0__RETRY__RETRY_STATUSES", "[502, 503]"),
+            ],
+        ).unwrap();
+
+        assert_eq!(config.db.engine, DBEngine::Mongo);
+        assert_eq!(config.db.port, 27018);
+
+        let hook = &config.hooks.as_ref().unwrap()["asset"][0];
+        assert_eq!(hook.username, Some("12345".to_string()));
+        assert_eq!(hook.rate_limit, Some(5.0));
+        assert!(!hook.retry.jitter);
+        assert_eq!(hook.retry.retry_statuses, vec![502, 503]);
+    }
+
+    #[test]
+    fn rejects_overrides_that_do_not_fit_their_field() {
+        let problems = [
+            ("MM_API_NOTIFY_DB__PORT", "abc", "`abc` is not an integer"),
+            ("MM_API_NOTIFY_DB__PORT", "70000", "`70000` is not between 0 and 65535"),
+            ("MM_API_NOTIFY_DB__ENGINE", "postgres", "`postgres` is not one of mongo, sqlite"),
+            ("MM_API_NOTIFY_ENABLE_HOOKS", "yes", "`yes` is not true or false"),
+            ("MM_API_NOTIFY_VOLATILE_FIELDS", "updated_at", "`updated_at` is not an array"),
+        ];
+
+        for &(name, value, problem) in &problems {
+            let err = load(MINIMAL, &[(name, value)]).unwrap_err();
+            assert_eq!(err.to_string(), format!("Invalid override {}: {}", name, problem));
+        }
+    }
+
+    #[test]
+    fn keeps_the_position_of_errors_in_the_file_with_overrides() {
+        let config_toml = MINIMAL.replace("ignore_skip = false", "ignore_skip = \"no\"");
+
+        match load(config_toml.as_str(), &[("MM_API_NOTIFY_MM__SECRET", "from-env")]) {
+            Err(ConfigError::Parse(err)) => assert_eq!(err.line_col(), Some((5, 14))),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_overrides_that_lead_nowhere() {
+        let config_toml = MINIMAL.to_string()
+            + "\n[hooks]\nasset = [{ url = \"https://example.org/\" }]\n";
+
+        let err = load(
+            config_toml.as_str(),
+            &[("MM_API_NOTIFY_HOOKS__ASSET__1__URL", "https://example.com/")],
+        ).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Invalid override MM_API_NOTIFY_HOOKS__ASSET__1__URL: `1` is not an index of \
+             `hooks.asset`"
+        );
+
+        let err = load(MINIMAL, &[("MM_API_NOTIFY_MM__KEY__ID", "x")]).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Invalid override MM_API_NOTIFY_MM__KEY__ID: `mm.key` does not have fields"
+        );
+    }
+
+    #[test]
+    fn resolves_file_and_env_references() {
+        let path = env::temp_dir().join("mm_api_notify_secret");
+        fs::write(&path, "from-file\n").unwrap();
+
+        let config_toml = MINIMAL
+            .replace("key = \"key\"", "key = \"env:MM_KEY\"")
+            .replace(
+                "secret = \"secret\"",
+                format!("secret = \"file:{}\"", path.display()).as_str(),
+            );
+
+        let config = load(config_toml.as_str(), &[("MM_KEY", "from-env")]).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.mm.key, "from-env");
+        assert_eq!(config.mm.secret, "from-file");
+
+        let err = load(config_toml.as_str(), &[]).unwrap_err();
+        assert_eq!(err.to_string(), "Could not resolve `mm.key`: MM_KEY is not set");
+    }
+
+    #[test]
+    fn resolves_references_in_every_string_field() {
+        let config_toml = format!(
+            "{}{}",
+            MINIMAL.replace("[log]", "[log]\nlocation = \"env:LOG\""),
+            r#"
+[[hooks.show]]
+url = "http://0.0.0.0/"
+username = "env:HOOK_USER"
+tls = { client_identity_password = "env:IDENTITY_PASSWORD" }
+"#
+        );
+
+        let vars = [
+            ("LOG", "from-env"),
+            ("HOOK_USER", "from-env"),
+            ("IDENTITY_PASSWORD", "from-env"),
+        ];
+        let config = load(config_toml.as_str(), &vars).unwrap();
+        let hook = &config.hooks.as_ref().unwrap()["show"][0];
+
+        assert_eq!(config.log.location, Some("from-env".to_string()));
+        assert_eq!(hook.username, Some("from-env".to_string()));
+        assert_eq!(
+            hook.tls.client_identity_password,
+            Some("from-env".to_string())
+        );
+    }
+
+    #[test]
+    fn starts_from_defaults_and_the_environment() {
+        let config = load(
+            "",
+            &[
+                ("MM_API_NOTIFY_MM__KEY", "key"),
+                ("MM_API_NOTIFY_MM__SECRET", "file:/nonexistent/mm_secret"),
+            ],
+        );
+
+        match config {
+            Err(ConfigError::Reference(ref key, _)) => assert_eq!(key, "mm.secret"),
+            other => panic!("expected a reference error, got {:?}", other),
+        }
+
+        let config = load(
+            "",
+            &[
+                ("MM_API_NOTIFY_MM__KEY", "key"),
+                ("MM_API_NOTIFY_MM__SECRET", "secret"),
+            ],
+        ).unwrap();
+
+        assert_eq!(config.db.engine, DBEngine::Mongo);
+        assert_eq!(config.db.host, "localhost");
+        assert_eq!(config.db.port, 27017);
+        assert_eq!(config.min_runtime_delta, 30);
+        assert_eq!(config.lookback_timeframe, 60);
+        assert_eq!(config.mm.changelog_max_timespan, 2505600);
+        assert!(config.validate().is_empty());
+    }
 }
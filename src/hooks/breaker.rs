@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use config::BreakerConfig;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half open"),
+        }
+    }
+}
+
+struct Circuit {
+    state: CircuitState,
+    failures: u32,
+    opened_at: Instant,
+    deferred: u64,
+}
+
+// The state of a circuit when it was summarized along with the number of deliveries that were
+// deferred since the previous summary
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitSummary {
+    pub target: String,
+    pub state: CircuitState,
+    pub failures: u32,
+    pub deferred: u64,
+}
+
+// Tracks consecutive failures for each hook target. A circuit opens after too many failures in a
+// row, and deliveries to it are deferred until a single probe is let through after the cooldown.
+// A successful probe closes the circuit and a failed one opens it again.
+#[derive(Clone)]
+pub struct CircuitBreakers {
+    config: BreakerConfig,
+    circuits: Arc<Mutex<HashMap<String, Circuit>>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(config: &BreakerConfig) -> CircuitBreakers {
+        CircuitBreakers {
+            config: config.clone(),
+            circuits: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Circuit>> {
+        self.circuits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Whether a delivery to the target should be attempted
+    pub fn allow(&self, target: &str) -> bool {
+        if self.config.failures == 0 {
+            return true;
+        }
+
+        let mut circuits = self.lock();
+
+        let circuit = match circuits.get_mut(target) {
+            Some(circuit) => circuit,
+            None => return true,
+        };
+
+        match circuit.state {
+            CircuitState::Closed => true,
+            CircuitState::Open
+                if circuit.opened_at.elapsed() >= Duration::from_millis(self.config.cooldown) =>
+            {
+                info!("Circuit for hook {} is half open. Sending a probe.", target);
+                circuit.state = CircuitState::HalfOpen;
+                true
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {
+                circuit.deferred += 1;
+                false
+            }
+        }
+    }
+
+    pub fn record(&self, target: &str, delivered: bool) {
+        if self.config.failures == 0 {
+            return;
+        }
+
+        let mut circuits = self.lock();
+
+        let circuit = circuits.entry(target.to_string()).or_insert(Circuit {
+            state: CircuitState::Closed,
+            failures: 0,
+            opened_at: Instant::now(),
+            deferred: 0,
+        });
+
+        if delivered {
+            if circuit.state != CircuitState::Closed {
+                info!("Circuit for hook {} is closed. Resuming deliveries.", target);
+            }
+
+            circuit.state = CircuitState::Closed;
+            circuit.failures = 0;
+            return;
+        }
+
+        circuit.failures += 1;
+
+        let opens = match circuit.state {
+            CircuitState::Closed => circuit.failures >= self.config.failures,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+
+        if opens {
+            warn!(
+                "Circuit for hook {} is open after {} consecutive failures. Deferring \
+                 deliveries for {}ms.",
+                target, circuit.failures, self.config.cooldown
+            );
+
+            circuit.state = CircuitState::Open;
+            circuit.opened_at = Instant::now();
+        }
+    }
+
+    // Circuits that are not closed or that deferred deliveries since the previous summary
+    pub fn summary(&self) -> Vec<CircuitSummary> {
+        let mut circuits = self.lock();
+
+        let mut summary: Vec<CircuitSummary> = circuits
+            .iter_mut()
+            .filter(|(_, circuit)| circuit.state != CircuitState::Closed || circuit.deferred > 0)
+            .map(|(target, circuit)| {
+                let deferred = circuit.deferred;
+                circuit.deferred = 0;
+
+                CircuitSummary {
+                    target: target.clone(),
+                    state: circuit.state,
+                    failures: circuit.failures,
+                    deferred,
+                }
+            })
+            .collect();
+
+        summary.sort_by(|a, b| a.target.cmp(&b.target));
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use config::BreakerConfig;
+
+    use super::{CircuitBreakers, CircuitState, CircuitSummary};
+
+    fn breakers(cooldown: u64) -> CircuitBreakers {
+        CircuitBreakers::new(&BreakerConfig {
+            failures: 2,
+            cooldown,
+        })
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breakers = breakers(60000);
+
+        breakers.record("hook", false);
+        assert!(breakers.allow("hook"));
+
+        breakers.record("hook", true);
+        breakers.record("hook", false);
+        assert!(breakers.allow("hook"));
+
+        breakers.record("hook", false);
+        assert!(!breakers.allow("hook"));
+        assert!(!breakers.allow("hook"));
+        assert!(breakers.allow("other"));
+
+        assert_eq!(
+            breakers.summary(),
+            vec![
+                CircuitSummary {
+                    target: "hook".to_string(),
+                    state: CircuitState::Open,
+                    failures: 2,
+                    deferred: 2,
+                },
+            ]
+        );
+
+        assert_eq!(breakers.summary()[0].deferred, 0);
+    }
+
+    #[test]
+    fn probes_once_after_cooldown() {
+        let breakers = breakers(50);
+
+        breakers.record("hook", false);
+        breakers.record("hook", false);
+        assert!(!breakers.allow("hook"));
+
+        thread::sleep(Duration::from_millis(60));
+
+        assert!(breakers.allow("hook"));
+        assert!(!breakers.allow("hook"));
+        assert_eq!(breakers.summary()[0].state, CircuitState::HalfOpen);
+
+        breakers.record("hook", false);
+        assert!(!breakers.allow("hook"));
+        assert_eq!(breakers.summary()[0].state, CircuitState::Open);
+
+        thread::sleep(Duration::from_millis(60));
+
+        assert!(breakers.allow("hook"));
+        breakers.record("hook", true);
+        assert!(breakers.allow("hook"));
+        assert!(breakers.summary().is_empty());
+    }
+
+    #[test]
+    fn never_opens_when_disabled() {
+        let breakers = CircuitBreakers::new(&BreakerConfig {
+            failures: 0,
+            cooldown: 60000,
+        });
+
+        for _ in 0..10 {
+            breakers.record("hook", false);
+        }
+
+        assert!(breakers.allow("hook"));
+        assert!(breakers.summary().is_empty());
+    }
+}
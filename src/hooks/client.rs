@@ -0,0 +1,612 @@
+extern crate openssl;
+extern crate reqwest;
+
+use self::openssl::error::ErrorStack;
+use self::openssl::pkcs12::Pkcs12;
+use self::openssl::pkey::PKey;
+use self::openssl::stack::Stack;
+use self::openssl::x509::X509;
+use self::reqwest::blocking::{Client, ClientBuilder};
+use self::reqwest::{Certificate, Identity, Proxy, Url};
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use config::{Hook, HttpConfig, ProxyConfig, TlsConfig};
+use hooks::CircuitBreakers;
+
+lazy_static! {
+    // Used by emitters that were not given the client from the runtime
+    static ref DEFAULT_CLIENT: HookClient =
+        HookClient::new(&HttpConfig::default()).expect("Failed to initialize hook client");
+}
+
+#[derive(Debug)]
+pub enum HookClientError {
+    Read(String, io::Error),
+    InvalidProxy(String),
+    InvalidIdentity(String),
+    Http(reqwest::Error),
+}
+
+impl fmt::Display for HookClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HookClientError::Read(ref path, ref err) => {
+                write!(f, "Failed to read {}: {}", path, err)
+            }
+            HookClientError::InvalidProxy(ref url) => write!(f, "Invalid proxy url {}", url),
+            HookClientError::InvalidIdentity(ref problem) => {
+                write!(f, "Invalid client certificate or key: {}", problem)
+            }
+            HookClientError::Http(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for HookClientError {
+    fn cause(&self) -> Option<&dyn Error> {
+        match *self {
+            HookClientError::Read(_, ref err) => Some(err),
+            HookClientError::Http(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ErrorStack> for HookClientError {
+    fn from(err: ErrorStack) -> HookClientError {
+        HookClientError::InvalidIdentity(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for HookClientError {
+    fn from(err: reqwest::Error) -> HookClientError {
+        HookClientError::Http(err)
+    }
+}
+
+pub type HookClientResult<T> = Result<T, HookClientError>;
+
+// A long lived HTTP client that keeps connections to hooks alive between notifications. It is
+// created once and shared by every emitter so that connections are pooled across imports. Hooks
+// with the same TLS settings share a connection pool.
+#[derive(Clone)]
+pub struct HookClient {
+    clients: Arc<Mutex<HashMap<TlsConfig, Client>>>,
+    breakers: CircuitBreakers,
+    config: HttpConfig,
+}
+
+impl HookClient {
+    pub fn new(config: &HttpConfig) -> HookClientResult<HookClient> {
+        let client = HookClient {
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            breakers: CircuitBreakers::new(&config.circuit_breaker),
+            config: config.clone(),
+        };
+
+        // Building the strictly verified client up front surfaces proxy errors at startup
+        client.client(&TlsConfig::default())?;
+
+        Ok(client)
+    }
+
+    pub fn shared() -> &'static HookClient {
+        &DEFAULT_CLIENT
+    }
+
+    pub fn breakers(&self) -> &CircuitBreakers {
+        &self.breakers
+    }
+
+    // Returns the client for the TLS settings, building it the first time they are seen
+    pub fn client(&self, tls: &TlsConfig) -> HookClientResult<Client> {
+        let mut clients = self.clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(client) = clients.get(tls) {
+            return Ok(client.clone());
+        }
+
+        let client = self.build(tls)?;
+        clients.insert(tls.clone(), client.clone());
+
+        Ok(client)
+    }
+
+    fn build(&self, tls: &TlsConfig) -> HookClientResult<Client> {
+        let mut builder = ClientBuilder::new()
+            .danger_accept_invalid_hostnames(tls.accept_invalid_hostnames)
+            .pool_max_idle_per_host(self.config.max_idle_connections)
+            .pool_idle_timeout(Duration::from_millis(self.config.idle_timeout))
+            .connect_timeout(Duration::from_millis(self.config.connect_timeout));
+
+        if let Some(ref path) = tls.ca_bundle {
+            builder = builder.add_root_certificate(Certificate::from_pem(&read(path)?)?);
+        }
+
+        if let Some(ref path) = tls.client_identity {
+            let password = tls.client_identity_password.as_deref().unwrap_or("");
+            builder = builder.identity(Identity::from_pkcs12_der(&read(path)?, password)?);
+        }
+
+        if let (Some(ref certificate), Some(ref key)) = (&tls.client_certificate, &tls.client_key) {
+            builder = builder.identity(pem_identity(&read(certificate)?, &read(key)?)?);
+        }
+
+        if let Some(ref proxy) = self.config.proxy {
+            builder = builder.proxy(proxy_for(proxy)?);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    // Requests to a hook time out after its own timeout when it has one
+    pub fn timeout(&self, hook: &Hook) -> Duration {
+        hook.timeout
+            .map_or(Duration::from_millis(self.config.timeout), Duration::from_millis)
+    }
+}
+
+fn read(path: &str) -> HookClientResult<Vec<u8>> {
+    fs::read(path).map_err(|err| HookClientError::Read(path.to_string(), err))
+}
+
+// native-tls only reads client identities from PKCS#12, so a certificate and key in PEM format are
+// bundled into one. Certificates after the first one in the file are sent as its chain.
+fn pem_identity(certificate: &[u8], key: &[u8]) -> HookClientResult<Identity> {
+    let mut certificates = X509::stack_from_pem(certificate)?.into_iter();
+
+    let leaf = certificates.next().ok_or_else(|| {
+        HookClientError::InvalidIdentity("the certificate file is empty".to_string())
+    })?;
+
+    let mut chain = Stack::new()?;
+
+    for certificate in certificates {
+        chain.push(certificate)?;
+    }
+
+    let key = PKey::private_key_from_pem(key)?;
+    let mut builder = Pkcs12::builder();
+    builder.ca(chain);
+
+    // Newer versions of openssl replace build with build2, which 0.10.30 does not have yet
+    #[allow(deprecated)]
+    let identity = builder.build("", "", &key, &leaf)?;
+
+    Ok(Identity::from_pkcs12_der(&identity.to_der()?, "")?)
+}
+
+// Hosts are excluded from the proxy when they match an entry or are a subdomain of it
+fn is_excluded(host: &str, no_proxy: &[String]) -> bool {
+    no_proxy.iter().any(|entry| {
+        let entry = entry.trim_start_matches('.');
+        entry == "*" || host == entry || host.ends_with(format!(".{}", entry).as_str())
+    })
+}
+
+pub fn proxy_for(config: &ProxyConfig) -> HookClientResult<Proxy> {
+    let proxy_url = Url::parse(config.url.as_str())
+        .map_err(|_| HookClientError::InvalidProxy(config.url.clone()))?;
+    let no_proxy = config.no_proxy.clone();
+
+    let proxy = Proxy::custom(move |url| match url.host_str() {
+        Some(host) if is_excluded(host, &no_proxy) => None,
+        _ => Some(proxy_url.clone()),
+    });
+
+    Ok(match config.username {
+        Some(ref username) => {
+            proxy.basic_auth(username, config.password.as_deref().unwrap_or(""))
+        }
+        None => proxy,
+    })
+}
+
+impl fmt::Debug for HookClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HookClient {{ config: {:?} }}", self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::openssl::asn1::Asn1Time;
+    use super::openssl::ec::{EcGroup, EcKey};
+    use super::openssl::hash::MessageDigest;
+    use super::openssl::nid::Nid;
+    use super::openssl::pkey::PKey;
+    use super::openssl::x509::{X509, X509NameBuilder};
+
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use config::{Hook, HttpConfig, ProxyConfig, RetryConfig, TlsConfig};
+    use hooks::test::{http_hook, show_hooks, test_dir, test_payload};
+    use hooks::{EmitResponse, Emitter, HookClient, HttpEmitter};
+
+    use super::HookClientError;
+
+    const KEEP_ALIVE: Duration = Duration::from_secs(5);
+
+    struct TestServer {
+        url: String,
+        connections: Arc<AtomicUsize>,
+        requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    // Reads a request from the connection, returning its request line
+    fn read_request(reader: &mut BufReader<TcpStream>) -> Option<String> {
+        let mut content_length = 0;
+        let mut request_line = String::new();
+
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return None;
+        }
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+
+            let header = line.trim().to_lowercase();
+
+            if header.is_empty() {
+                break;
+            }
+
+            if let Some(value) = header.strip_prefix("content-length:") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+
+        Some(request_line.trim().to_string())
+    }
+
+    // Answers every request on a connection with an empty 200 until the client hangs up
+    fn serve(stream: TcpStream, requests: Arc<Mutex<Vec<String>>>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+
+        while let Some(request) = read_request(&mut reader) {
+            requests.lock().unwrap().push(request);
+
+            writer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        }
+    }
+
+    // Answers the first request on a connection, but resets it when it is kept alive for another
+    fn serve_once(stream: TcpStream, requests: Arc<Mutex<Vec<String>>>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+
+        if let Some(request) = read_request(&mut reader) {
+            requests.lock().unwrap().push(request);
+
+            writer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        }
+
+        // Closing the connection while the next request is unread resets it
+        let _ = writer.peek(&mut [0]);
+    }
+
+    // Like common servers, connections are closed once they have been idle for 5 seconds. A
+    // request that arrives after that resets the connection, as when it is sent just as the
+    // server closes the connection.
+    fn serve_with_keep_alive(stream: TcpStream, requests: Arc<Mutex<Vec<String>>>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+
+        loop {
+            let idle_since = Instant::now();
+
+            if writer.peek(&mut [0]).unwrap_or(0) == 0 || idle_since.elapsed() > KEEP_ALIVE {
+                return;
+            }
+
+            match read_request(&mut reader) {
+                Some(request) => requests.lock().unwrap().push(request),
+                None => return,
+            }
+
+            writer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        }
+    }
+
+    // Starts a server that records the connections and requests that it receives
+    fn test_server() -> TestServer {
+        test_server_with(serve)
+    }
+
+    fn test_server_with(handler: fn(TcpStream, Arc<Mutex<Vec<String>>>)) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let counter = connections.clone();
+        let log = requests.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let stream = stream.unwrap();
+                let log = log.clone();
+                thread::spawn(move || handler(stream, log));
+            }
+        });
+
+        TestServer {
+            url,
+            connections,
+            requests,
+        }
+    }
+
+    fn emit_update(client: &HookClient, hook: Hook) -> EmitResponse {
+        let payload = test_payload();
+        let config = show_hooks(vec![hook]);
+
+        HttpEmitter::new(&payload, &config).with_client(client).update()
+    }
+
+    fn delivered(url: &str) -> EmitResponse {
+        EmitResponse {
+            success: vec![url.to_string()],
+            failure: vec![],
+            attempts: 1,
+        }
+    }
+
+    #[test]
+    fn reuses_connections_between_notifications() {
+        let server = test_server();
+        let client = HookClient::new(&HttpConfig::default()).unwrap();
+
+        for _ in 0..5 {
+            assert_eq!(
+                emit_update(&client, http_hook(server.url.as_str())),
+                delivered(server.url.as_str())
+            );
+        }
+
+        assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+        assert_eq!(server.requests.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn leaves_reset_connections_to_the_retry_policy() {
+        let server = test_server_with(serve_once);
+        let client = HookClient::new(&HttpConfig::default()).unwrap();
+
+        let hook = Hook {
+            retry: RetryConfig {
+                max_attempts: 2,
+                base_delay: 1,
+                max_delay: 1,
+                jitter: false,
+                ..RetryConfig::default()
+            },
+            ..http_hook(server.url.as_str())
+        };
+
+        let retried = EmitResponse {
+            success: vec![server.url.clone()],
+            failure: vec![],
+            attempts: 2,
+        };
+
+        assert_eq!(
+            emit_update(&client, hook.clone()),
+            delivered(server.url.as_str())
+        );
+        assert_eq!(emit_update(&client, hook), retried);
+
+        assert_eq!(server.connections.load(Ordering::SeqCst), 2);
+        assert_eq!(server.requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn closes_idle_connections_before_hooks_do() {
+        let server = test_server_with(serve_with_keep_alive);
+        let client = HookClient::new(&HttpConfig::default()).unwrap();
+
+        assert_eq!(
+            emit_update(&client, http_hook(server.url.as_str())),
+            delivered(server.url.as_str())
+        );
+
+        thread::sleep(KEEP_ALIVE + Duration::from_millis(100));
+
+        assert_eq!(
+            emit_update(&client, http_hook(server.url.as_str())),
+            delivered(server.url.as_str())
+        );
+
+        assert_eq!(server.connections.load(Ordering::SeqCst), 2);
+        assert_eq!(server.requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn prefers_hook_timeout() {
+        let client = HookClient::new(&HttpConfig {
+            timeout: 1000,
+            ..HttpConfig::default()
+        }).unwrap();
+
+        let hook = Hook {
+            timeout: Some(250),
+            ..Hook::default()
+        };
+
+        assert_eq!(client.timeout(&Hook::default()).as_secs(), 1);
+        assert_eq!(client.timeout(&hook).subsec_millis(), 250);
+    }
+
+    #[test]
+    fn shares_clients_between_hooks_with_the_same_tls_settings() {
+        let client = HookClient::new(&HttpConfig::default()).unwrap();
+
+        let insecure = TlsConfig {
+            accept_invalid_hostnames: true,
+            ..TlsConfig::default()
+        };
+
+        client.client(&TlsConfig::default()).unwrap();
+        client.client(&insecure).unwrap();
+        client.client(&insecure).unwrap();
+
+        assert_eq!(client.clients.lock().unwrap().len(), 2);
+    }
+
+    // Writes a self-signed certificate and its key in PEM format, returning their paths
+    fn write_pem_identity(dir: &Path) -> (String, String) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "mm_api_notify").unwrap();
+        let name = name.build();
+
+        let mut certificate = X509::builder().unwrap();
+        certificate.set_version(2).unwrap();
+        certificate.set_subject_name(&name).unwrap();
+        certificate.set_issuer_name(&name).unwrap();
+        certificate.set_pubkey(&key).unwrap();
+        certificate
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        certificate
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        certificate.sign(&key, MessageDigest::sha256()).unwrap();
+
+        let certificate_path = dir.join("client.crt");
+        let key_path = dir.join("client.key");
+
+        fs::write(&certificate_path, certificate.build().to_pem().unwrap()).unwrap();
+        fs::write(&key_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+        (
+            certificate_path.to_string_lossy().into_owned(),
+            key_path.to_string_lossy().into_owned(),
+        )
+    }
+
+    #[test]
+    fn reads_pem_client_certificates() {
+        let dir = test_dir("client_pem_identity_test");
+        let (certificate, key) = write_pem_identity(&dir);
+        let client = HookClient::new(&HttpConfig::default()).unwrap();
+
+        let tls = TlsConfig {
+            client_certificate: Some(certificate.clone()),
+            client_key: Some(key),
+            ..TlsConfig::default()
+        };
+
+        assert!(client.client(&tls).is_ok());
+
+        let swapped = TlsConfig {
+            client_key: Some(certificate),
+            ..tls
+        };
+
+        match client.client(&swapped) {
+            Err(HookClientError::InvalidIdentity(_)) => (),
+            other => panic!("expected an invalid identity, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fails_hooks_with_unreadable_ca_bundle() {
+        let client = HookClient::new(&HttpConfig::default()).unwrap();
+
+        let hook = Hook {
+            tls: TlsConfig {
+                ca_bundle: Some("/nonexistent/mm_api_notify_ca.pem".to_string()),
+                ..TlsConfig::default()
+            },
+            ..http_hook("https://hooks.invalid/")
+        };
+
+        let emit_resp = EmitResponse {
+            success: vec![],
+            failure: vec!["https://hooks.invalid/".to_string()],
+            attempts: 1,
+        };
+
+        assert_eq!(emit_update(&client, hook), emit_resp);
+    }
+
+    #[test]
+    fn sends_requests_through_proxy() {
+        let proxy = test_server();
+
+        let client = HookClient::new(&HttpConfig {
+            proxy: Some(ProxyConfig {
+                url: proxy.url.clone(),
+                username: None,
+                password: None,
+                no_proxy: vec![],
+            }),
+            ..HttpConfig::default()
+        }).unwrap();
+
+        assert_eq!(
+            emit_update(&client, http_hook("http://hooks.invalid/notify/")),
+            delivered("http://hooks.invalid/notify/")
+        );
+
+        assert_eq!(
+            *proxy.requests.lock().unwrap(),
+            vec!["POST http://hooks.invalid/notify/ HTTP/1.1".to_string()]
+        );
+    }
+
+    #[test]
+    fn bypasses_proxy_for_excluded_hosts() {
+        let proxy = test_server();
+        let server = test_server();
+
+        let client = HookClient::new(&HttpConfig {
+            proxy: Some(ProxyConfig {
+                url: proxy.url.clone(),
+                username: None,
+                password: None,
+                no_proxy: vec!["127.0.0.1".to_string()],
+            }),
+            ..HttpConfig::default()
+        }).unwrap();
+
+        assert_eq!(
+            emit_update(&client, http_hook(server.url.as_str())),
+            delivered(server.url.as_str())
+        );
+
+        assert_eq!(proxy.connections.load(Ordering::SeqCst), 0);
+        assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,198 @@
+extern crate serde_json;
+extern crate sha2;
+
+use self::sha2::{Digest, Sha256};
+use serde_json::Value as Json;
+
+use config::Hook;
+use hooks::{EmitAction, Payload};
+
+pub const SPEC_VERSION: &str = "1.0";
+pub const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+pub const DATA_CONTENT_TYPE: &str = "application/json";
+
+const TYPE_PREFIX: &str = "org.pbs.mm";
+
+// A CloudEvents 1.0 event describing a change to a Media Manager object. The data of the event is
+// the body that the hook would otherwise receive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CloudEvent {
+    pub id: String,
+    pub source: String,
+    pub event_type: String,
+    pub subject: String,
+    pub time: Option<String>,
+    pub data: Json,
+}
+
+fn string_attribute(payload: &Payload, key: &str) -> Option<String> {
+    payload
+        .data
+        .get(key)
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string())
+}
+
+impl CloudEvent {
+    pub fn new(hook: &Hook, payload: &Payload, action: &EmitAction) -> CloudEvent {
+        let event_type = format!("{}.{}.{}", TYPE_PREFIX, payload.object_type(), action.event());
+        let subject = string_attribute(payload, "id").unwrap_or_default();
+        let time = string_attribute(payload, "updated_at");
+        let data = payload.render(hook);
+
+        // Ids are derived from the event so that redelivering an event does not change its id
+        let digest = Sha256::new()
+            .chain(event_type.as_bytes())
+            .chain(b"\n")
+            .chain(data.to_string().as_bytes())
+            .finalize();
+
+        let id = digest
+            .iter()
+            .take(16)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        CloudEvent {
+            id,
+            source: hook.cloudevents.source.clone(),
+            event_type,
+            subject,
+            time,
+            data,
+        }
+    }
+
+    // The event in the structured content mode, with its attributes and data in a single document
+    pub fn structured(&self) -> Json {
+        let mut event = json!({
+            "specversion": SPEC_VERSION,
+            "id": self.id,
+            "source": self.source,
+            "type": self.event_type,
+            "subject": self.subject,
+            "datacontenttype": DATA_CONTENT_TYPE,
+            "data": self.data,
+        });
+
+        if let Some(ref time) = self.time {
+            event["time"] = Json::String(time.clone());
+        }
+
+        event
+    }
+
+    // The attributes of the event as headers for the binary content mode, where the data is sent
+    // as the body of the request
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("ce-specversion", SPEC_VERSION.to_string()),
+            ("ce-id", self.id.clone()),
+            ("ce-source", self.source.clone()),
+            ("ce-type", self.event_type.clone()),
+            ("ce-subject", self.subject.clone()),
+        ];
+
+        if let Some(ref time) = self.time {
+            headers.push(("ce-time", time.clone()));
+        }
+
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{Hook, PayloadFormat};
+    use hooks::{EmitAction, Payload};
+
+    use super::CloudEvent;
+
+    fn test_payload() -> Payload {
+        Payload::new(
+            json!({
+                "id": "test-asset",
+                "type": "asset",
+                "updated_at": "2017-01-01T00:00:00Z",
+                "parent": null
+            }).as_object()
+                .unwrap()
+                .to_owned(),
+        )
+    }
+
+    fn cloudevents_hook() -> Hook {
+        Hook {
+            format: PayloadFormat::CloudEvents,
+            ..Hook::default()
+        }
+    }
+
+    #[test]
+    fn describes_changes_to_objects() {
+        let payload = test_payload();
+        let event = CloudEvent::new(&cloudevents_hook(), &payload, &EmitAction::Update);
+
+        assert_eq!(event.event_type, "org.pbs.mm.asset.updated");
+        assert_eq!(event.subject, "test-asset");
+        assert_eq!(event.source, "/mm_api_notify");
+        assert_eq!(event.time, Some("2017-01-01T00:00:00Z".to_string()));
+        assert_eq!(event.data, json!({ "data": payload.data }));
+        assert_eq!(event.id.len(), 32);
+
+        let deleted = CloudEvent::new(&cloudevents_hook(), &payload, &EmitAction::Delete);
+
+        assert_eq!(deleted.event_type, "org.pbs.mm.asset.deleted");
+        assert!(deleted.id != event.id);
+
+        let created = CloudEvent::new(&cloudevents_hook(), &payload, &EmitAction::Create);
+
+        assert_eq!(created.event_type, "org.pbs.mm.asset.created");
+    }
+
+    #[test]
+    fn keeps_ids_for_the_same_event() {
+        let payload = test_payload();
+
+        let first = CloudEvent::new(&cloudevents_hook(), &payload, &EmitAction::Update);
+        let second = CloudEvent::new(&cloudevents_hook(), &payload, &EmitAction::Update);
+
+        assert_eq!(first.id, second.id);
+    }
+
+    #[test]
+    fn renders_structured_events() {
+        let payload = test_payload();
+        let event = CloudEvent::new(&cloudevents_hook(), &payload, &EmitAction::Update);
+
+        assert_eq!(
+            event.structured(),
+            json!({
+                "specversion": "1.0",
+                "id": event.id,
+                "source": "/mm_api_notify",
+                "type": "org.pbs.mm.asset.updated",
+                "subject": "test-asset",
+                "time": "2017-01-01T00:00:00Z",
+                "datacontenttype": "application/json",
+                "data": { "data": payload.data }
+            })
+        );
+    }
+
+    #[test]
+    fn omits_missing_times() {
+        let payload = Payload::new(
+            json!({ "id": "test-asset", "type": "asset" })
+                .as_object()
+                .unwrap()
+                .to_owned(),
+        );
+
+        let event = CloudEvent::new(&cloudevents_hook(), &payload, &EmitAction::Delete);
+
+        assert!(event.structured().get("time").is_none());
+        assert!(event.headers().iter().all(|&(name, _)| name != "ce-time"));
+        assert_eq!(event.headers()[3], ("ce-type", "org.pbs.mm.asset.deleted".to_string()));
+    }
+}
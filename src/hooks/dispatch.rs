@@ -0,0 +1,111 @@
+use config::{Hook, HookConfig, HookKind};
+use hooks::{DeliveryPool, EmitAction, EmitResponse, Emitter, ExecEmitter, FileEmitter,
+            HookClient, HttpEmitter, Outbox, Payload, Submission};
+
+// Emits to every kind of hook configured for the payload type by handing each hook to the
+// emitter for its kind
+#[derive(Debug)]
+pub struct HookEmitter<'a, 'b> {
+    payload: &'a Payload,
+    config: &'b HookConfig,
+    outbox: Option<Outbox<'b>>,
+    client: Option<&'b HookClient>,
+    pool: Option<&'b DeliveryPool>,
+}
+
+impl<'a, 'b> HookEmitter<'a, 'b> {
+    // Records each delivery in the outbox so that it can be recovered if it does not complete
+    pub fn with_outbox(mut self, outbox: Outbox<'b>) -> HookEmitter<'a, 'b> {
+        self.outbox = Some(outbox);
+        self
+    }
+
+    pub fn with_client(mut self, client: &'b HookClient) -> HookEmitter<'a, 'b> {
+        self.client = Some(client);
+        self
+    }
+
+    // Hands deliveries off to the pool instead of sending them before returning. The response
+    // from emitting is then empty and the results are collected by waiting on the pool.
+    pub fn with_pool(mut self, pool: &'b DeliveryPool) -> HookEmitter<'a, 'b> {
+        self.pool = Some(pool);
+        self
+    }
+
+    // Hands the deliveries for the payload off to the pool, returning them so that the caller can
+    // wait on them. Deliveries that completed in the meantime are settled in the outbox so that
+    // they do not pile up until the pool is waited on.
+    pub fn submit(&self, pool: &DeliveryPool, action: EmitAction) -> Vec<Submission> {
+        let submitted = self.hooks()
+            .into_iter()
+            .filter_map(|hook| {
+                let entry = self.outbox
+                    .and_then(|outbox| outbox.record(hook, action, self.payload));
+
+                pool.submit(hook, action, self.payload, entry)
+            })
+            .collect();
+
+        if let Some(outbox) = self.outbox {
+            pool.settle(outbox);
+        }
+
+        submitted
+    }
+}
+
+impl<'a, 'b> Emitter<'a, 'b> for HookEmitter<'a, 'b> {
+    fn new(payload: &'a Payload, config: &'b HookConfig) -> HookEmitter<'a, 'b> {
+        HookEmitter {
+            payload,
+            config,
+            outbox: None,
+            client: None,
+            pool: None,
+        }
+    }
+
+    fn payload(&self) -> &Payload {
+        self.payload
+    }
+
+    fn config(&self) -> &HookConfig {
+        self.config
+    }
+
+    fn handles(&self, _: &Hook) -> bool {
+        true
+    }
+
+    fn emit_hook(&self, hook: &Hook, action: &EmitAction) -> Option<(String, bool, i64)> {
+        match hook.kind {
+            HookKind::Http => {
+                let emitter = HttpEmitter::new(self.payload, self.config);
+
+                match self.client {
+                    Some(client) => emitter.with_client(client).emit_hook(hook, action),
+                    None => emitter.emit_hook(hook, action),
+                }
+            }
+            HookKind::File => FileEmitter::new(self.payload, self.config).emit_hook(hook, action),
+            HookKind::Exec => ExecEmitter::new(self.payload, self.config).emit_hook(hook, action),
+        }
+    }
+
+    fn emit(&self, action: EmitAction) -> EmitResponse {
+        if let Some(pool) = self.pool {
+            self.submit(pool, action);
+            return EmitResponse::default();
+        }
+
+        self.hooks()
+            .into_iter()
+            .filter_map(|hook| match self.outbox {
+                Some(ref outbox) => outbox.deliver(self, hook, action),
+                None => self.emit_hook(hook, &action),
+            })
+            .fold(EmitResponse::default(), |response, (hook, status, attempts)| {
+                response.record(hook, status, attempts)
+            })
+    }
+}
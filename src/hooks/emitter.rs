@@ -1,19 +1,44 @@
 use hooks::Payload;
-use config::HookConfig;
+use config::{Hook, HookConfig};
 
-#[derive(Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum EmitAction {
+    Create,
     Delete,
     Update,
 }
 
-#[derive(Debug, PartialEq)]
+impl EmitAction {
+    // The name of the event that the action notifies receivers of
+    pub fn event(&self) -> &'static str {
+        match *self {
+            EmitAction::Create => "created",
+            EmitAction::Delete => "deleted",
+            EmitAction::Update => "updated",
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
 pub struct EmitResponse {
     pub success: Vec<String>,
     pub failure: Vec<String>,
+    pub attempts: i64,
 }
 
 impl EmitResponse {
+    pub fn record(mut self, hook: String, delivered: bool, attempts: i64) -> EmitResponse {
+        if delivered {
+            self.success.push(hook)
+        } else {
+            self.failure.push(hook)
+        }
+
+        self.attempts += attempts;
+        self
+    }
+
     pub fn results(&self) -> (i64, i64) {
         (self.success.len() as i64, self.failure.len() as i64)
     }
@@ -21,6 +46,50 @@ impl EmitResponse {
 
 pub trait Emitter<'a, 'b> {
     fn new(payload: &'a Payload, config: &'b HookConfig) -> Self;
-    fn delete(&self) -> EmitResponse;
-    fn update(&self) -> EmitResponse;
+    fn payload(&self) -> &Payload;
+    fn config(&self) -> &HookConfig;
+
+    // Whether the emitter is able to deliver to the hook
+    fn handles(&self, hook: &Hook) -> bool;
+
+    // Delivers to a single hook, returning its target, whether it was delivered and the number of
+    // attempts that were made. A delivery with no attempts was deferred without being sent.
+    fn emit_hook(&self, hook: &Hook, action: &EmitAction) -> Option<(String, bool, i64)>;
+
+    // Hooks for the payload type whose filters match the payload. Hooks that are filtered out are
+    // not sent to and are not counted as failures.
+    fn hooks(&self) -> Vec<&Hook> {
+        let payload = self.payload();
+
+        self.config()
+            .get(payload.object_type())
+            .map(|hooks| {
+                hooks
+                    .iter()
+                    .filter(|hook| self.handles(hook) && payload.matches(hook))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn emit(&self, action: EmitAction) -> EmitResponse {
+        self.hooks()
+            .into_iter()
+            .filter_map(|hook| self.emit_hook(hook, &action))
+            .fold(EmitResponse::default(), |response, (hook, status, attempts)| {
+                response.record(hook, status, attempts)
+            })
+    }
+
+    fn create(&self) -> EmitResponse {
+        self.emit(EmitAction::Create)
+    }
+
+    fn delete(&self) -> EmitResponse {
+        self.emit(EmitAction::Delete)
+    }
+
+    fn update(&self) -> EmitResponse {
+        self.emit(EmitAction::Update)
+    }
 }
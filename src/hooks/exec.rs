@@ -0,0 +1,227 @@
+extern crate serde_json;
+
+use serde_json::Value as Json;
+
+use std::io::Write;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config::{Hook, HookConfig, HookKind};
+use hooks::{EmitAction, Emitter, Payload};
+
+const DEFAULT_TIMEOUT: u64 = 30000;
+const POLL_INTERVAL: u64 = 10;
+
+// Runs a local command for each notification with the payload on its stdin
+#[derive(Debug, PartialEq)]
+pub struct ExecEmitter<'a, 'b> {
+    payload: &'a Payload,
+    config: &'b HookConfig,
+}
+
+impl<'a, 'b> ExecEmitter<'a, 'b> {
+    fn env_value(&self, key: &str) -> String {
+        match self.payload.data.get(key) {
+            Some(Json::String(value)) => value.clone(),
+            _ => "".to_string(),
+        }
+    }
+
+    fn run(&self, hook: &Hook, command: &str, action: &EmitAction) -> bool {
+        let input = match serde_json::to_vec(&self.payload.body(hook, action)) {
+            Ok(input) => input,
+            Err(err) => {
+                warn!("Failed to serialize payload for command hook {}: {}", command, err);
+                return false;
+            }
+        };
+
+        let child = Command::new(command)
+            .args(&hook.args)
+            .env("MM_NOTIFY_ACTION", action.event())
+            .env("MM_NOTIFY_TYPE", self.env_value("type"))
+            .env("MM_NOTIFY_ID", self.env_value("id"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                warn!("Failed to start command hook {}: {}", command, err);
+                return false;
+            }
+        };
+
+        // The payload is written from another thread so that a command which never reads its
+        // input is still subject to the timeout
+        if let Some(mut stdin) = child.stdin.take() {
+            thread::spawn(move || {
+                let _ = stdin.write_all(&input);
+            });
+        }
+
+        let timeout = Duration::from_millis(hook.timeout.unwrap_or(DEFAULT_TIMEOUT));
+
+        match wait_with_timeout(&mut child, timeout) {
+            Some(status) if status.success() => true,
+            Some(status) => {
+                warn!("Command hook {} exited with {}", command, status);
+                false
+            }
+            None => {
+                warn!(
+                    "Command hook {} did not finish within {}ms and was killed",
+                    command,
+                    timeout.as_secs() * 1000 + timeout.subsec_millis() as u64
+                );
+
+                let _ = child.kill();
+                let _ = child.wait();
+                false
+            }
+        }
+    }
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let started = Instant::now();
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if started.elapsed() < timeout => {
+                thread::sleep(Duration::from_millis(POLL_INTERVAL))
+            }
+            Ok(None) => return None,
+            Err(err) => {
+                warn!("Failed to wait on command hook: {}", err);
+                return None;
+            }
+        }
+    }
+}
+
+impl<'a, 'b> Emitter<'a, 'b> for ExecEmitter<'a, 'b> {
+    fn new(payload: &'a Payload, config: &'b HookConfig) -> ExecEmitter<'a, 'b> {
+        ExecEmitter { payload, config }
+    }
+
+    fn payload(&self) -> &Payload {
+        self.payload
+    }
+
+    fn config(&self) -> &HookConfig {
+        self.config
+    }
+
+    fn handles(&self, hook: &Hook) -> bool {
+        hook.kind == HookKind::Exec
+    }
+
+    fn emit_hook(&self, hook: &Hook, action: &EmitAction) -> Option<(String, bool, i64)> {
+        hook.command
+            .as_ref()
+            .map(|command| (command.clone(), self.run(hook, command, action), 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use serde_json::Value as Json;
+
+    use std::env;
+    use std::fs;
+
+    use config::{Hook, HookKind};
+    use hooks::test::{shell_hook, show_hooks, test_payload};
+    use hooks::{EmitResponse, Emitter, ExecEmitter};
+
+    #[test]
+    fn passes_payload_and_env_to_command() {
+        let output = env::temp_dir().join("mm_api_notify_exec_passes_payload_test.json");
+        let _ = fs::remove_file(&output);
+
+        let script = format!(
+            "test \"$MM_NOTIFY_ACTION\" = updated && test \"$MM_NOTIFY_TYPE\" = show && \
+             test \"$MM_NOTIFY_ID\" = test-child && cat > {}",
+            output.to_str().unwrap()
+        );
+
+        let payload = test_payload();
+        let config = show_hooks(vec![shell_hook(script.as_str())]);
+        let emit = ExecEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec!["sh".to_string()],
+            failure: vec![],
+            attempts: 1,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+
+        let written: Json = serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(
+            written,
+            json!({ "data": payload.data, "action": "updated" })
+        );
+    }
+
+    #[test]
+    fn fails_on_non_zero_exit() {
+        let payload = test_payload();
+        let config = show_hooks(vec![shell_hook("exit 3")]);
+        let emit = ExecEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![],
+            failure: vec!["sh".to_string()],
+            attempts: 1,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+    }
+
+    #[test]
+    fn fails_when_command_times_out() {
+        let hook = Hook {
+            timeout: Some(100),
+            ..shell_hook("sleep 5")
+        };
+
+        let payload = test_payload();
+        let config = show_hooks(vec![hook]);
+        let emit = ExecEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![],
+            failure: vec!["sh".to_string()],
+            attempts: 1,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+    }
+
+    #[test]
+    fn fails_when_command_is_missing() {
+        let hook = Hook {
+            kind: HookKind::Exec,
+            command: Some("/nonexistent/mm_api_notify_hook".to_string()),
+            ..Hook::default()
+        };
+
+        let payload = test_payload();
+        let config = show_hooks(vec![hook]);
+        let emit = ExecEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![],
+            failure: vec!["/nonexistent/mm_api_notify_hook".to_string()],
+            attempts: 1,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+    }
+}
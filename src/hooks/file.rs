@@ -0,0 +1,320 @@
+extern crate chrono;
+extern crate serde_json;
+
+use self::chrono::offset::Utc;
+use self::chrono::{DateTime, SecondsFormat};
+use self::serde_json::Value as Json;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use config::{Hook, HookConfig, HookKind, RotationConfig};
+use hooks::{EmitAction, Emitter, Payload};
+
+lazy_static! {
+    // Serializes rotation and appends so that lines from concurrent imports are never interleaved
+    static ref FILE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+// Appends each notification as a line of JSON to a local file
+#[derive(Debug, PartialEq)]
+pub struct FileEmitter<'a, 'b> {
+    payload: &'a Payload,
+    config: &'b HookConfig,
+}
+
+impl<'a, 'b> FileEmitter<'a, 'b> {
+    fn line(&self, hook: &Hook, action: &EmitAction) -> serde_json::Result<Vec<u8>> {
+        let mut line = serde_json::to_vec(&json!({
+            "action": action.event(),
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "payload": self.payload.body(hook, action),
+        }))?;
+
+        line.push(b'\n');
+        Ok(line)
+    }
+
+    fn append(&self, hook: &Hook, path: &str, action: &EmitAction) -> bool {
+        let line = match self.line(hook, action) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("Failed to serialize payload for file hook {}: {}", path, err);
+                return false;
+            }
+        };
+
+        let _guard = FILE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let written = rotate(path, &hook.rotate).and_then(|_| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(&line))
+        });
+
+        match written {
+            Ok(_) => true,
+            Err(err) => {
+                warn!("Failed writing to file hook {}: {}", path, err);
+                false
+            }
+        }
+    }
+}
+
+// Files are started by their first notification, whose timestamp gives their age. Unlike the
+// creation time of the file, it is available on every filesystem.
+fn started_at(path: &str) -> io::Result<Option<DateTime<Utc>>> {
+    let mut first_line = String::new();
+    BufReader::new(File::open(path)?).read_line(&mut first_line)?;
+
+    Ok(serde_json::from_str::<Json>(first_line.as_str())
+        .ok()
+        .and_then(|line| {
+            line["timestamp"]
+                .as_str()
+                .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        })
+        .map(|timestamp| timestamp.with_timezone(&Utc)))
+}
+
+fn needs_rotation(path: &str, policy: &RotationConfig) -> io::Result<bool> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    if metadata.len() == 0 {
+        return Ok(false);
+    }
+
+    let too_large = policy
+        .max_bytes
+        .map_or(false, |max_bytes| metadata.len() >= max_bytes);
+
+    let too_old = match policy.max_age {
+        Some(max_age) => started_at(path)?.map_or(false, |started_at| {
+            Utc::now().signed_duration_since(started_at).num_seconds() >= max_age as i64
+        }),
+        None => false,
+    };
+
+    Ok(too_large || too_old)
+}
+
+// Moves a full or expired file aside with the time that it was rotated appended to its name
+fn rotate(path: &str, policy: &RotationConfig) -> io::Result<()> {
+    if !needs_rotation(path, policy)? {
+        return Ok(());
+    }
+
+    let now: DateTime<Utc> = Utc::now();
+    let base = format!("{}.{}", path, now.format("%Y%m%dT%H%M%S"));
+
+    let mut rotated = base.clone();
+    let mut suffix = 1;
+
+    while Path::new(rotated.as_str()).exists() {
+        rotated = format!("{}.{}", base, suffix);
+        suffix += 1;
+    }
+
+    info!("Rotating file hook {} to {}", path, rotated);
+
+    fs::rename(path, rotated)
+}
+
+impl<'a, 'b> Emitter<'a, 'b> for FileEmitter<'a, 'b> {
+    fn new(payload: &'a Payload, config: &'b HookConfig) -> FileEmitter<'a, 'b> {
+        FileEmitter { payload, config }
+    }
+
+    fn payload(&self) -> &Payload {
+        self.payload
+    }
+
+    fn config(&self) -> &HookConfig {
+        self.config
+    }
+
+    fn handles(&self, hook: &Hook) -> bool {
+        hook.kind == HookKind::File
+    }
+
+    fn emit_hook(&self, hook: &Hook, action: &EmitAction) -> Option<(String, bool, i64)> {
+        hook.path
+            .as_ref()
+            .map(|path| (path.clone(), self.append(hook, path, action), 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use serde_json::Value as Json;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    use config::{Hook, HookKind, RotationConfig};
+    use hooks::test::{http_hook, show_hooks, test_dir, test_payload};
+    use hooks::{EmitResponse, Emitter, FileEmitter};
+
+    fn file_hook(path: &str, rotate: RotationConfig) -> Hook {
+        Hook {
+            kind: HookKind::File,
+            path: Some(path.to_string()),
+            rotate,
+            ..Hook::default()
+        }
+    }
+
+    fn read_lines(path: &PathBuf) -> Vec<Json> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn appends_notifications_as_json_lines() {
+        let dir = test_dir("file_appends_test");
+        let path = dir.join("hook.ndjson");
+        let path_str = path.to_str().unwrap().to_string();
+
+        let config = show_hooks(vec![file_hook(path_str.as_str(), RotationConfig::default())]);
+
+        let payload = test_payload();
+        let emit = FileEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![path_str.clone()],
+            failure: vec![],
+            attempts: 1,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+        assert_eq!(emit.delete(), emit_resp);
+
+        let lines = read_lines(&path);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["action"], json!("updated"));
+        assert_eq!(lines[1]["action"], json!("deleted"));
+        assert_eq!(
+            lines[0]["payload"],
+            json!({ "data": payload.data, "action": "updated" })
+        );
+        assert_eq!(lines[1]["payload"]["action"], json!("deleted"));
+        assert!(lines[0]["timestamp"].is_string());
+    }
+
+    #[test]
+    fn ignores_other_kinds_of_hooks() {
+        let config = show_hooks(vec![http_hook("http://0.0.0.0/")]);
+
+        let payload = test_payload();
+        let emit = FileEmitter::new(&payload, &config);
+
+        assert_eq!(emit.update(), EmitResponse::default());
+    }
+
+    #[test]
+    fn rotates_files_that_exceed_max_bytes() {
+        let dir = test_dir("file_rotates_bytes_test");
+        let path = dir.join("hook.ndjson");
+        let path_str = path.to_str().unwrap().to_string();
+
+        let rotate = RotationConfig {
+            max_bytes: Some(10),
+            ..RotationConfig::default()
+        };
+
+        let config = show_hooks(vec![file_hook(path_str.as_str(), rotate)]);
+
+        let payload = test_payload();
+        let emit = FileEmitter::new(&payload, &config);
+
+        emit.update();
+        emit.update();
+        emit.update();
+
+        assert_eq!(read_lines(&path).len(), 1);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn rotates_files_that_exceed_max_age() {
+        let dir = test_dir("file_rotates_age_test");
+        let path = dir.join("hook.ndjson");
+        let path_str = path.to_str().unwrap().to_string();
+
+        let hook = file_hook(
+            path_str.as_str(),
+            RotationConfig {
+                max_age: Some(3600),
+                ..RotationConfig::default()
+            },
+        );
+
+        let config = show_hooks(vec![hook]);
+
+        let payload = test_payload();
+        let emit = FileEmitter::new(&payload, &config);
+
+        emit.update();
+        emit.update();
+
+        assert_eq!(read_lines(&path).len(), 2);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let hook = file_hook(
+            path_str.as_str(),
+            RotationConfig {
+                max_age: Some(0),
+                ..RotationConfig::default()
+            },
+        );
+
+        let config = show_hooks(vec![hook]);
+        let emit = FileEmitter::new(&payload, &config);
+        emit.update();
+
+        assert_eq!(read_lines(&path).len(), 1);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn ages_files_by_their_first_notification() {
+        let dir = test_dir("file_ages_by_first_line_test");
+        let path = dir.join("hook.ndjson");
+        let path_str = path.to_str().unwrap().to_string();
+
+        fs::write(
+            &path,
+            "{\"action\":\"updated\",\"timestamp\":\"2017-01-01T00:00:00.000Z\",\"payload\":{}}\n",
+        ).unwrap();
+
+        let rotate = RotationConfig {
+            max_age: Some(3600),
+            ..RotationConfig::default()
+        };
+
+        let config = show_hooks(vec![file_hook(path_str.as_str(), rotate)]);
+
+        let payload = test_payload();
+        let emit = FileEmitter::new(&payload, &config);
+
+        emit.update();
+        emit.update();
+
+        assert_eq!(read_lines(&path).len(), 2);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,203 @@
+extern crate serde_json;
+
+use serde_json::Map;
+use serde_json::Value as Json;
+
+use config::HookFilter;
+use hooks::Payload;
+
+// Collects the type and id of the payload object followed by each of its parents
+fn ancestry(data: &Map<String, Json>) -> Vec<(&str, &str)> {
+    let mut chain = vec![];
+    let mut current = Some(data);
+
+    while let Some(node) = current {
+        let object_type = node.get("type").and_then(|t| t.as_str());
+        let id = node.get("id").and_then(|id| id.as_str());
+
+        if let (Some(object_type), Some(id)) = (object_type, id) {
+            chain.push((object_type, id));
+        }
+
+        current = node.get("parent").and_then(|parent| parent.as_object());
+    }
+
+    chain
+}
+
+fn in_ancestry(chain: &[(&str, &str)], object_type: &str, ids: &[String]) -> bool {
+    ids.is_empty() || chain
+        .iter()
+        .any(|&(t, id)| t == object_type && ids.iter().any(|allowed| allowed == id))
+}
+
+// A list of values matches any of its entries
+fn attribute_matches(value: Option<&Json>, expected: &Json) -> bool {
+    match (value, expected) {
+        (Some(value), Json::Array(options)) => options.iter().any(|option| option == value),
+        (Some(value), expected) => value == expected,
+        (None, _) => false,
+    }
+}
+
+// Determines whether a hook with the filter should receive the payload. An excluded id matches
+// the object itself or any of its parents.
+pub fn matches(filter: &HookFilter, payload: &Payload) -> bool {
+    let chain = ancestry(&payload.data);
+
+    if chain
+        .iter()
+        .any(|&(_, id)| filter.exclude_ids.iter().any(|excluded| excluded == id))
+    {
+        return false;
+    }
+
+    // Payloads that only identify an object, such as deletes, have no parents or attributes
+    // that the remaining rules could be checked against
+    if !payload.data.contains_key("parent") {
+        return true;
+    }
+
+    in_ancestry(&chain, "franchise", &filter.franchises)
+        && in_ancestry(&chain, "show", &filter.shows)
+        && filter
+            .attributes
+            .iter()
+            .all(|(key, expected)| attribute_matches(payload.data.get(key), expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value as Json;
+
+    use std::collections::BTreeMap;
+
+    use config::HookFilter;
+    use hooks::Payload;
+
+    use super::matches;
+
+    fn asset_payload() -> Payload {
+        Payload::new(
+            json!({
+                "id": "test-asset",
+                "type": "asset",
+                "object_type": "full_length",
+                "parent": {
+                    "id": "test-episode",
+                    "type": "episode",
+                    "parent": {
+                        "id": "test-season",
+                        "type": "season",
+                        "parent": {
+                            "id": "test-show",
+                            "type": "show",
+                            "parent": {
+                                "id": "test-franchise",
+                                "type": "franchise",
+                                "parent": null
+                            }
+                        }
+                    }
+                }
+            }).as_object()
+                .unwrap()
+                .to_owned(),
+        )
+    }
+
+    fn delete_payload() -> Payload {
+        Payload::new(
+            json!({
+                "id": "test-asset",
+                "type": "asset"
+            }).as_object()
+                .unwrap()
+                .to_owned(),
+        )
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = HookFilter::default();
+
+        assert!(matches(&filter, &asset_payload()));
+        assert!(matches(&filter, &delete_payload()));
+    }
+
+    #[test]
+    fn matches_on_ancestry() {
+        let in_franchise = HookFilter {
+            franchises: vec!["other-franchise".to_string(), "test-franchise".to_string()],
+            ..HookFilter::default()
+        };
+
+        let in_show = HookFilter {
+            shows: vec!["test-show".to_string()],
+            ..HookFilter::default()
+        };
+
+        let in_other_show = HookFilter {
+            franchises: vec!["test-franchise".to_string()],
+            shows: vec!["other-show".to_string()],
+            ..HookFilter::default()
+        };
+
+        assert!(matches(&in_franchise, &asset_payload()));
+        assert!(matches(&in_show, &asset_payload()));
+        assert!(!matches(&in_other_show, &asset_payload()));
+    }
+
+    #[test]
+    fn matches_on_attributes() {
+        let mut full_length = BTreeMap::new();
+        full_length.insert(
+            "object_type".to_string(),
+            Json::String("full_length".to_string()),
+        );
+
+        let mut clips = BTreeMap::new();
+        clips.insert("object_type".to_string(), json!(["clip", "preview"]));
+
+        let mut missing = BTreeMap::new();
+        missing.insert("duration".to_string(), json!(60));
+
+        let filter = |attributes| HookFilter {
+            attributes,
+            ..HookFilter::default()
+        };
+
+        assert!(matches(&filter(full_length), &asset_payload()));
+        assert!(!matches(&filter(clips), &asset_payload()));
+        assert!(!matches(&filter(missing), &asset_payload()));
+    }
+
+    #[test]
+    fn excludes_ids_and_their_descendants() {
+        let excludes_asset = HookFilter {
+            exclude_ids: vec!["test-asset".to_string()],
+            ..HookFilter::default()
+        };
+
+        let excludes_show = HookFilter {
+            exclude_ids: vec!["test-show".to_string()],
+            ..HookFilter::default()
+        };
+
+        assert!(!matches(&excludes_asset, &asset_payload()));
+        assert!(!matches(&excludes_asset, &delete_payload()));
+        assert!(!matches(&excludes_show, &asset_payload()));
+        assert!(matches(&excludes_show, &delete_payload()));
+    }
+
+    #[test]
+    fn only_checks_ids_of_delete_payloads() {
+        let filter = HookFilter {
+            shows: vec!["other-show".to_string()],
+            ..HookFilter::default()
+        };
+
+        assert!(!matches(&filter, &asset_payload()));
+        assert!(matches(&filter, &delete_payload()));
+    }
+}
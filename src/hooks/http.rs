@@ -1,111 +1,221 @@
+extern crate chrono;
 extern crate reqwest;
 extern crate serde_json;
 
-use self::reqwest::header::USER_AGENT;
-use self::reqwest::{Method, StatusCode};
+use self::chrono::offset::Utc;
+use self::reqwest::header::{CONTENT_TYPE, RETRY_AFTER, USER_AGENT};
+use self::reqwest::Method;
 use log::warn;
 use serde_json::Value as Json;
 
-use std::collections::BTreeMap;
+use std::cmp;
+use std::thread;
+use std::time::Duration;
 
-use config::HookConfig;
-use hooks::{EmitAction, EmitResponse, Emitter, Payload};
+use mm_api_notify::signature;
 
-#[derive(Debug, PartialEq)]
+use config::{CloudEventsMode, Hook, HookConfig, HookKind, PayloadFormat};
+use hooks::{cloudevents, retry};
+use hooks::{CloudEvent, EmitAction, Emitter, HookClient, Payload};
+
+#[derive(Debug)]
 pub struct HttpEmitter<'a, 'b> {
     payload: &'a Payload,
     config: &'b HookConfig,
+    client: Option<&'b HookClient>,
+}
+
+// The body of a request, its content type and any additional headers
+type Encoded = (Vec<u8>, &'static str, Vec<(&'static str, String)>);
+
+enum Attempt {
+    Delivered,
+    Failed {
+        retryable: bool,
+        retry_after: Option<Duration>,
+    },
 }
 
 impl<'a, 'b> HttpEmitter<'a, 'b> {
-    fn payload_type(&self) -> &str {
-        self.payload
-            .data
-            .get("type")
-            .and_then(|type_json| type_json.as_str())
-            .unwrap_or("")
+    // Sends requests through the given client instead of the process wide default
+    pub fn with_client(mut self, client: &'b HookClient) -> HttpEmitter<'a, 'b> {
+        self.client = Some(client);
+        self
     }
 
-    fn hooks(&self) -> Option<&Vec<BTreeMap<String, String>>> {
-        self.config.get(self.payload_type())
+    fn client(&self) -> &HookClient {
+        match self.client {
+            Some(client) => client,
+            None => HookClient::shared(),
+        }
     }
 
-    fn emit(&self, method: EmitAction) -> EmitResponse {
-        let hook_results = self
-            .hooks()
-            .unwrap_or(&vec![])
-            .iter()
-            .filter_map(|hook| {
-                hook.get("url").map(|base_url| {
-                    let user = hook
-                        .get("username")
-                        .map(|user_ref| user_ref.to_owned())
-                        .unwrap_or("".to_string());
-                    let pass = hook.get("password").map(|pass_ref| pass_ref.to_owned());
-
-                    let mut url = base_url.clone();
-
-                    if method == EmitAction::Delete {
-                        if let &Json::String(ref id) = &self.payload.data["id"] {
-                            url.push_str(id);
-                            url.push('/');
-                        }
+    fn deliver(&self, hook: &Hook, url: &str, method: &EmitAction) -> (bool, i64) {
+        let client = self.client();
+
+        let max_attempts = cmp::max(hook.retry.max_attempts, 1);
+        let mut attempt = 1;
+
+        loop {
+            match self.attempt(client, hook, url, method) {
+                Attempt::Delivered => return (true, attempt as i64),
+                Attempt::Failed {
+                    retryable,
+                    retry_after,
+                } => {
+                    if !retryable || attempt >= max_attempts {
+                        return (false, attempt as i64);
                     }
 
-                    (url, user, pass)
-                })
-            })
-            .map(|(url, user, pass)| {
-                reqwest::blocking::ClientBuilder::new()
-                    .danger_accept_invalid_hostnames(true)
-                    .build()
-                    .map(|client| {
-                        let mut req = match method {
-                            EmitAction::Delete => client.request(Method::DELETE, url.as_str()),
-                            EmitAction::Update => client.post(url.as_str()),
-                        };
-                        req = req
-                            .basic_auth(user, pass)
-                            .header(USER_AGENT, "MM-API-NOTIFY")
-                            .json(&self.payload);
-                        let response = req.send();
-                        if let Err(send_err) = &response {
-                            warn!("Failed sending to remote hook: {}", send_err);
-                        }
-                        let status = response.ok().map_or_else(
-                            || false,
-                            |resp| match resp.status() {
-                                StatusCode::OK => true,
-                                failure => {
-                                    warn!(
-                                        "Remote hook returned status: {} with message: {}",
-                                        resp.status(),
-                                        resp.text().unwrap_or(
-                                            "Unable to read message from remote".to_string()
-                                        )
-                                    );
-                                    false
-                                }
-                            },
-                        );
-
-                        (url.clone(), status)
-                    })
-                    .unwrap_or((url, false))
-            })
-            .fold((vec![], vec![]), |(mut pass, mut fail), (hook, status)| {
-                if status == true {
-                    pass.push(hook.to_owned())
-                } else {
-                    fail.push(hook.to_owned())
+                    let wait = retry::delay(&hook.retry, attempt, retry_after);
+
+                    info!(
+                        "Retrying remote hook {} in {}ms (attempt {} of {})",
+                        url,
+                        wait.as_secs() * 1000 + wait.subsec_millis() as u64,
+                        attempt + 1,
+                        max_attempts
+                    );
+
+                    thread::sleep(wait);
+                    attempt += 1;
                 }
+            }
+        }
+    }
 
-                (pass, fail)
-            });
+    // Serializes the body of a request to the hook along with its content type and any CloudEvents
+    // attributes that are sent as headers
+    fn encode(
+        &self,
+        hook: &Hook,
+        method: &EmitAction,
+    ) -> serde_json::Result<Encoded> {
+        match (hook.format, hook.cloudevents.mode) {
+            (PayloadFormat::Default, _) => Ok((
+                serde_json::to_vec(&self.payload.body(hook, method))?,
+                cloudevents::DATA_CONTENT_TYPE,
+                vec![],
+            )),
+            (PayloadFormat::CloudEvents, CloudEventsMode::Structured) => {
+                let event = CloudEvent::new(hook, self.payload, method);
+
+                Ok((
+                    serde_json::to_vec(&event.structured())?,
+                    cloudevents::STRUCTURED_CONTENT_TYPE,
+                    vec![],
+                ))
+            }
+            (PayloadFormat::CloudEvents, CloudEventsMode::Binary) => {
+                let event = CloudEvent::new(hook, self.payload, method);
+
+                Ok((
+                    serde_json::to_vec(&event.data)?,
+                    cloudevents::DATA_CONTENT_TYPE,
+                    event.headers(),
+                ))
+            }
+        }
+    }
+
+    fn attempt(&self, client: &HookClient, hook: &Hook, url: &str, method: &EmitAction) -> Attempt {
+        let user = hook.username.clone().unwrap_or("".to_string());
+        let pass = hook.password.clone();
+
+        let http = match client.client(&hook.tls) {
+            Ok(http) => http,
+            Err(err) => {
+                warn!("Failed to configure client for remote hook {}: {}", url, err);
+
+                return Attempt::Failed {
+                    retryable: false,
+                    retry_after: None,
+                };
+            }
+        };
+
+        let name = match *method {
+            EmitAction::Create => &hook.methods.create,
+            EmitAction::Delete => &hook.methods.delete,
+            EmitAction::Update => &hook.methods.update,
+        };
+
+        let req = match Method::from_bytes(name.to_uppercase().as_bytes()) {
+            Ok(http_method) => http.request(http_method, url),
+            Err(err) => {
+                warn!("Invalid method {} for remote hook {}: {}", name, url, err);
+
+                return Attempt::Failed {
+                    retryable: false,
+                    retry_after: None,
+                };
+            }
+        };
+
+        let (body, content_type, attributes) = match self.encode(hook, method) {
+            Ok(encoded) => encoded,
+            Err(err) => {
+                warn!("Failed to serialize payload for remote hook {}: {}", url, err);
+
+                return Attempt::Failed {
+                    retryable: false,
+                    retry_after: None,
+                };
+            }
+        };
 
-        EmitResponse {
-            success: hook_results.0,
-            failure: hook_results.1,
+        let mut req = req.basic_auth(user, pass)
+            .timeout(client.timeout(hook))
+            .header(USER_AGENT, "MM-API-NOTIFY")
+            .header(CONTENT_TYPE, content_type);
+
+        for (name, value) in attributes {
+            req = req.header(name, value);
+        }
+
+        // The signature covers the exact bytes that are sent so that receivers can verify them
+        if let Some(ref secret) = hook.secret {
+            let timestamp = Utc::now().timestamp();
+
+            req = req.header(signature::TIMESTAMP_HEADER, timestamp.to_string())
+                .header(
+                    signature::SIGNATURE_HEADER,
+                    signature::sign(secret, timestamp, &body),
+                );
+        }
+
+        let response = req.body(body).send();
+
+        match response {
+            Ok(resp) => match resp.status() {
+                status if status.is_success() => Attempt::Delivered,
+                status => {
+                    let retry_after = resp.headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(retry::parse_retry_after);
+
+                    warn!(
+                        "Remote hook returned status: {} with message: {}",
+                        status,
+                        resp.text()
+                            .unwrap_or("Unable to read message from remote".to_string())
+                    );
+
+                    Attempt::Failed {
+                        retryable: retry::is_retryable_status(&hook.retry, status.as_u16()),
+                        retry_after,
+                    }
+                }
+            },
+            Err(send_err) => {
+                warn!("Failed sending to remote hook: {}", send_err);
+
+                Attempt::Failed {
+                    retryable: true,
+                    retry_after: None,
+                }
+            }
         }
     }
 }
@@ -115,15 +225,45 @@ impl<'a, 'b> Emitter<'a, 'b> for HttpEmitter<'a, 'b> {
         HttpEmitter {
             payload: payload,
             config: config,
+            client: None,
         }
     }
 
-    fn delete(&self) -> EmitResponse {
-        self.emit(EmitAction::Delete)
+    fn payload(&self) -> &Payload {
+        self.payload
+    }
+
+    fn config(&self) -> &HookConfig {
+        self.config
+    }
+
+    fn handles(&self, hook: &Hook) -> bool {
+        hook.kind == HookKind::Http
     }
 
-    fn update(&self) -> EmitResponse {
-        self.emit(EmitAction::Update)
+    fn emit_hook(&self, hook: &Hook, method: &EmitAction) -> Option<(String, bool, i64)> {
+        hook.url.as_ref().map(|base_url| {
+            let mut url = base_url.clone();
+
+            if *method == EmitAction::Delete {
+                if let &Json::String(ref id) = &self.payload.data["id"] {
+                    url.push_str(id);
+                    url.push('/');
+                }
+            }
+
+            // Deliveries to a hook whose circuit is open are not attempted
+            let breakers = self.client().breakers();
+
+            if !breakers.allow(base_url) {
+                return (url, false, 0);
+            }
+
+            let (status, attempts) = self.deliver(hook, url.as_str(), method);
+            breakers.record(base_url, status);
+
+            (url, status, attempts)
+        })
     }
 }
 
@@ -131,12 +271,15 @@ impl<'a, 'b> Emitter<'a, 'b> for HttpEmitter<'a, 'b> {
 mod tests {
 
     use mockito;
-    use mockito::mock;
+    use mockito::{mock, Matcher};
     use serde_json::Value as Json;
 
     use std::collections::BTreeMap;
 
-    use hooks::{EmitResponse, Emitter, HttpEmitter, Payload};
+    use config::{BreakerConfig, CloudEventsConfig, CloudEventsMode, Hook, HookFilter, HttpConfig,
+                 PayloadFormat, RetryConfig};
+    use hooks::test::{http_hook, show_hooks, test_payload};
+    use hooks::{CloudEvent, EmitAction, EmitResponse, Emitter, HookClient, HttpEmitter, Payload};
 
     #[test]
     fn emits_update() {
@@ -151,8 +294,10 @@ mod tests {
         let mut endpoint = mockito::SERVER_URL.to_string();
         endpoint.push_str("/http_emit_update_test/");
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), endpoint.to_string());
+        let hook = Hook {
+            url: Some(endpoint.to_string()),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook]);
@@ -168,12 +313,13 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let emit_resp = EmitResponse {
                 success: vec![endpoint.to_string()],
                 failure: vec![],
+                attempts: 1,
             };
 
             assert_eq!(emit.update(), emit_resp)
@@ -196,7 +342,7 @@ mod tests {
             }
         });
 
-        let req_data = json!({ "data": payload_data });
+        let req_data = json!({ "data": payload_data, "action": "updated" });
 
         let _m = mock("POST", "/http_update_contains_object_test/")
             .with_status(200)
@@ -206,21 +352,22 @@ mod tests {
         let mut endpoint = mockito::SERVER_URL.to_string();
         endpoint.push_str("/http_update_contains_object_test/");
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), endpoint.to_string());
+        let hook = Hook {
+            url: Some(endpoint.to_string()),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook]);
 
         let payload_map = payload_data.as_object().unwrap();
-        let payload = Payload {
-            data: payload_map.to_owned(),
-        };
+        let payload = Payload::new(payload_map.to_owned());
         let emit = HttpEmitter::new(&payload, &config);
 
         let emit_resp = EmitResponse {
             success: vec![endpoint.to_string()],
             failure: vec![],
+            attempts: 1,
         };
 
         assert_eq!(emit.update(), emit_resp);
@@ -236,8 +383,10 @@ mod tests {
         let mut endpoint = mockito::SERVER_URL.to_string();
         endpoint.push_str("/http_emit_delete_test/");
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), endpoint.to_string());
+        let hook = Hook {
+            url: Some(endpoint.to_string()),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook]);
@@ -253,7 +402,7 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let mut delete_endpoint = endpoint.clone();
@@ -262,6 +411,7 @@ mod tests {
             let emit_resp = EmitResponse {
                 success: vec![delete_endpoint],
                 failure: vec![],
+                attempts: 1,
             };
 
             assert_eq!(emit.delete(), emit_resp)
@@ -292,11 +442,15 @@ mod tests {
         let mut endpoint2 = mockito::SERVER_URL.to_string();
         endpoint2.push_str("/http_calls_all_hooks_for_type_test_2/");
 
-        let mut hook1 = BTreeMap::new();
-        hook1.insert("url".to_string(), endpoint1.to_string());
+        let hook1 = Hook {
+            url: Some(endpoint1.to_string()),
+            ..Hook::default()
+        };
 
-        let mut hook2 = BTreeMap::new();
-        hook2.insert("url".to_string(), endpoint2.to_string());
+        let hook2 = Hook {
+            url: Some(endpoint2.to_string()),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook1, hook2]);
@@ -312,12 +466,13 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let emit_resp = EmitResponse {
                 success: vec![endpoint1.to_string(), endpoint2.to_string()],
                 failure: vec![],
+                attempts: 2,
             };
 
             assert_eq!(emit.update(), emit_resp)
@@ -339,14 +494,18 @@ mod tests {
         let mut endpoint1 = mockito::SERVER_URL.to_string();
         endpoint1.push_str("/http_only_calls_hooks_for_type_test/");
 
-        let mut hook1 = BTreeMap::new();
-        hook1.insert("url".to_string(), endpoint1.to_string());
+        let hook1 = Hook {
+            url: Some(endpoint1.to_string()),
+            ..Hook::default()
+        };
 
         let mut endpoint2 = mockito::SERVER_URL.to_string();
         endpoint2.push_str("/http_only_calls_hooks_for_type_test_2/");
 
-        let mut hook2 = BTreeMap::new();
-        hook2.insert("url".to_string(), endpoint2.to_string());
+        let hook2 = Hook {
+            url: Some(endpoint2.to_string()),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook1]);
@@ -363,12 +522,13 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let emit_resp = EmitResponse {
                 success: vec![endpoint1.to_string()],
                 failure: vec![],
+                attempts: 1,
             };
 
             assert_eq!(emit.update(), emit_resp)
@@ -387,7 +547,7 @@ mod tests {
             .with_body(test_response.as_str())
             .create();
 
-        let hook = BTreeMap::new();
+        let hook = Hook::default();
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook]);
@@ -403,12 +563,13 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let emit_resp = EmitResponse {
                 success: vec![],
                 failure: vec![],
+                attempts: 0,
             };
 
             assert_eq!(emit.update(), emit_resp)
@@ -430,8 +591,10 @@ mod tests {
         let mut endpoint = mockito::SERVER_URL.to_string();
         endpoint.push_str("/http_handles_hooks_without_auth_test/");
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), endpoint.to_string());
+        let hook = Hook {
+            url: Some(endpoint.to_string()),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook]);
@@ -447,12 +610,13 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let emit_resp = EmitResponse {
                 success: vec![endpoint.to_string()],
                 failure: vec![],
+                attempts: 1,
             };
 
             assert_eq!(emit.update(), emit_resp)
@@ -475,10 +639,12 @@ mod tests {
         let mut endpoint = mockito::SERVER_URL.to_string();
         endpoint.push_str("/http_handles_hooks_with_auth_test/");
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), endpoint.to_string());
-        hook.insert("username".to_string(), "hello".to_string());
-        hook.insert("password".to_string(), "world".to_string());
+        let hook = Hook {
+            url: Some(endpoint.to_string()),
+            username: Some("hello".to_string()),
+            password: Some("world".to_string()),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook]);
@@ -494,12 +660,13 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let emit_resp = EmitResponse {
                 success: vec![endpoint.to_string()],
                 failure: vec![],
+                attempts: 1,
             };
 
             assert_eq!(emit.update(), emit_resp)
@@ -522,8 +689,10 @@ mod tests {
         let mut endpoint = mockito::SERVER_URL.to_string();
         endpoint.push_str("/http_emits_json_content_type_header_test/");
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), endpoint.to_string());
+        let hook = Hook {
+            url: Some(endpoint.to_string()),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert("show".to_string(), vec![hook]);
@@ -539,12 +708,13 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let emit_resp = EmitResponse {
                 success: vec![endpoint.to_string()],
                 failure: vec![],
+                attempts: 1,
             };
 
             assert_eq!(emit.update(), emit_resp)
@@ -552,4 +722,388 @@ mod tests {
             panic!("Failed to create payload map")
         }
     }
+
+    #[test]
+    fn update_contains_changes_when_requested() {
+        let payload_data = json!({
+            "id": "test-child",
+            "type": "show",
+            "title": "New Title",
+            "parent": null
+        });
+
+        let changes = json!({
+            "title": {
+                "old": "Old Title",
+                "new": "New Title"
+            }
+        });
+
+        let with_changes = json!({ "data": payload_data, "changes": changes, "action": "updated" });
+        let without_changes = json!({ "data": payload_data, "action": "updated" });
+
+        // The mock server closes connections after responding, so they must not be reused
+        let _m1 = mock("POST", "/http_update_contains_changes_test/")
+            .with_status(200)
+            .with_header("connection", "close")
+            .match_body(with_changes.to_string().as_str())
+            .create();
+
+        let _m2 = mock("POST", "/http_update_contains_changes_test_2/")
+            .with_status(200)
+            .match_body(without_changes.to_string().as_str())
+            .create();
+
+        let mut endpoint1 = mockito::SERVER_URL.to_string();
+        endpoint1.push_str("/http_update_contains_changes_test/");
+
+        let mut endpoint2 = mockito::SERVER_URL.to_string();
+        endpoint2.push_str("/http_update_contains_changes_test_2/");
+
+        let hook1 = Hook {
+            url: Some(endpoint1.to_string()),
+            include_changes: true,
+            ..Hook::default()
+        };
+
+        let hook2 = Hook {
+            url: Some(endpoint2.to_string()),
+            ..Hook::default()
+        };
+
+        let config = show_hooks(vec![hook1, hook2]);
+
+        let mut payload = Payload::new(payload_data.as_object().unwrap().to_owned());
+        payload.changes = Some(changes.as_object().unwrap().to_owned());
+
+        let emit = HttpEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![endpoint1.to_string(), endpoint2.to_string()],
+            failure: vec![],
+            attempts: 2,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+    }
+
+    #[test]
+    fn signs_requests_for_hooks_with_secret() {
+        // The mock server closes connections after responding, so they must not be reused
+        let _m1 = mock("POST", "/http_signs_requests_test/")
+            .with_status(200)
+            .with_header("connection", "close")
+            .match_header(
+                "x-mm-notify-signature",
+                Matcher::Regex("^sha256=[0-9a-f]{64}$".to_string()),
+            )
+            .match_header(
+                "x-mm-notify-timestamp",
+                Matcher::Regex("^[0-9]+$".to_string()),
+            )
+            .create();
+
+        let _m2 = mock("POST", "/http_signs_requests_test_2/")
+            .with_status(200)
+            .match_header("x-mm-notify-signature", Matcher::Missing)
+            .create();
+
+        let mut endpoint1 = mockito::SERVER_URL.to_string();
+        endpoint1.push_str("/http_signs_requests_test/");
+
+        let mut endpoint2 = mockito::SERVER_URL.to_string();
+        endpoint2.push_str("/http_signs_requests_test_2/");
+
+        let hook1 = Hook {
+            url: Some(endpoint1.to_string()),
+            secret: Some("test-secret".to_string()),
+            ..Hook::default()
+        };
+
+        let hook2 = Hook {
+            url: Some(endpoint2.to_string()),
+            ..Hook::default()
+        };
+
+        let config = show_hooks(vec![hook1, hook2]);
+
+        let payload = test_payload();
+        let emit = HttpEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![endpoint1.to_string(), endpoint2.to_string()],
+            failure: vec![],
+            attempts: 2,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+    }
+
+    #[test]
+    fn skips_hooks_that_do_not_match_filters() {
+        let _m1 = mock("POST", "/http_skips_unmatched_hooks_test/")
+            .with_status(200)
+            .create();
+
+        let m2 = mock("POST", "/http_skips_unmatched_hooks_test_2/")
+            .with_status(200)
+            .expect(0)
+            .create();
+
+        let mut endpoint1 = mockito::SERVER_URL.to_string();
+        endpoint1.push_str("/http_skips_unmatched_hooks_test/");
+
+        let mut endpoint2 = mockito::SERVER_URL.to_string();
+        endpoint2.push_str("/http_skips_unmatched_hooks_test_2/");
+
+        let hook1 = Hook {
+            url: Some(endpoint1.to_string()),
+            filter: HookFilter {
+                franchises: vec!["test-franchise".to_string()],
+                ..HookFilter::default()
+            },
+            ..Hook::default()
+        };
+
+        let hook2 = Hook {
+            url: Some(endpoint2.to_string()),
+            filter: HookFilter {
+                franchises: vec!["other-franchise".to_string()],
+                ..HookFilter::default()
+            },
+            ..Hook::default()
+        };
+
+        let config = show_hooks(vec![hook1, hook2]);
+
+        let payload = Payload::new(
+            json!({
+                "id": "test-child",
+                "type": "show",
+                "parent": {
+                    "id": "test-franchise",
+                    "type": "franchise",
+                    "parent": null
+                }
+            }).as_object()
+                .unwrap()
+                .to_owned(),
+        );
+        let emit = HttpEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![endpoint1.to_string()],
+            failure: vec![],
+            attempts: 1,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+        m2.assert();
+    }
+
+    fn retrying_hook(endpoint: &str) -> Hook {
+        Hook {
+            url: Some(endpoint.to_string()),
+            retry: RetryConfig {
+                max_attempts: 3,
+                base_delay: 1,
+                max_delay: 10,
+                jitter: false,
+                retry_statuses: vec![503],
+            },
+            ..Hook::default()
+        }
+    }
+
+    #[test]
+    fn retries_retryable_failures() {
+        let m = mock("POST", "/http_retries_retryable_failures_test/")
+            .with_status(503)
+            .with_header("retry-after", "0")
+            .expect(3)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/http_retries_retryable_failures_test/");
+
+        let config = show_hooks(vec![retrying_hook(endpoint.as_str())]);
+
+        let payload = test_payload();
+        let emit = HttpEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![],
+            failure: vec![endpoint.to_string()],
+            attempts: 3,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+        m.assert();
+    }
+
+    #[test]
+    fn does_not_retry_other_failures() {
+        let m = mock("POST", "/http_does_not_retry_other_failures_test/")
+            .with_status(400)
+            .expect(1)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/http_does_not_retry_other_failures_test/");
+
+        let config = show_hooks(vec![retrying_hook(endpoint.as_str())]);
+
+        let payload = test_payload();
+        let emit = HttpEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![],
+            failure: vec![endpoint.to_string()],
+            attempts: 1,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+        m.assert();
+    }
+
+    #[test]
+    fn accepts_any_success_status() {
+        let m = mock("POST", "/http_accepts_any_success_status_test/")
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/http_accepts_any_success_status_test/");
+
+        let config = show_hooks(vec![retrying_hook(endpoint.as_str())]);
+
+        let payload = test_payload();
+        let emit = HttpEmitter::new(&payload, &config);
+
+        let emit_resp = EmitResponse {
+            success: vec![endpoint.to_string()],
+            failure: vec![],
+            attempts: 1,
+        };
+
+        assert_eq!(emit.update(), emit_resp);
+        m.assert();
+    }
+
+    #[test]
+    fn short_circuits_hooks_with_open_circuits() {
+        let m = mock("POST", "/http_short_circuits_test/")
+            .with_status(503)
+            .expect(2)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/http_short_circuits_test/");
+
+        let client = HookClient::new(&HttpConfig {
+            circuit_breaker: BreakerConfig {
+                failures: 2,
+                cooldown: 60000,
+            },
+            ..HttpConfig::default()
+        }).unwrap();
+
+        let config = show_hooks(vec![http_hook(endpoint.as_str())]);
+
+        let payload = test_payload();
+        let emit = HttpEmitter::new(&payload, &config).with_client(&client);
+
+        let failed = EmitResponse {
+            success: vec![],
+            failure: vec![endpoint.to_string()],
+            attempts: 1,
+        };
+
+        let deferred = EmitResponse {
+            success: vec![],
+            failure: vec![endpoint.to_string()],
+            attempts: 0,
+        };
+
+        assert_eq!(emit.update(), failed);
+        assert_eq!(emit.update(), failed);
+        assert_eq!(emit.update(), deferred);
+        assert_eq!(emit.update(), deferred);
+        m.assert();
+    }
+
+    fn cloudevents_hook(endpoint: &str, mode: CloudEventsMode) -> Hook {
+        Hook {
+            url: Some(endpoint.to_string()),
+            format: PayloadFormat::CloudEvents,
+            cloudevents: CloudEventsConfig {
+                mode,
+                source: "https://example.org/mm".to_string(),
+            },
+            ..Hook::default()
+        }
+    }
+
+    fn cloudevents_payload() -> Payload {
+        let mut payload = test_payload();
+        payload
+            .data
+            .insert("updated_at".to_string(), json!("2017-01-01T00:00:00Z"));
+        payload
+    }
+
+    #[test]
+    fn sends_structured_cloudevents() {
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/http_sends_structured_cloudevents_test/");
+
+        let hook = cloudevents_hook(endpoint.as_str(), CloudEventsMode::Structured);
+        let payload = cloudevents_payload();
+        let event = CloudEvent::new(&hook, &payload, &EmitAction::Update);
+
+        let m = mock("POST", "/http_sends_structured_cloudevents_test/")
+            .match_header("content-type", "application/cloudevents+json")
+            .match_header("ce-id", Matcher::Missing)
+            .match_body(event.structured().to_string().as_str())
+            .with_status(200)
+            .create();
+
+        let config = show_hooks(vec![hook]);
+
+        let emit = HttpEmitter::new(&payload, &config);
+
+        assert_eq!(emit.update().results(), (1, 0));
+        assert_eq!(event.structured()["type"], json!("org.pbs.mm.show.updated"));
+        m.assert();
+    }
+
+    #[test]
+    fn sends_binary_cloudevents() {
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/http_sends_binary_cloudevents_test/");
+
+        let hook = cloudevents_hook(endpoint.as_str(), CloudEventsMode::Binary);
+        let payload = cloudevents_payload();
+        let event = CloudEvent::new(&hook, &payload, &EmitAction::Update);
+
+        let m = mock("POST", "/http_sends_binary_cloudevents_test/")
+            .match_header("content-type", "application/json")
+            .match_header("ce-specversion", "1.0")
+            .match_header("ce-id", event.id.as_str())
+            .match_header("ce-source", "https://example.org/mm")
+            .match_header("ce-type", "org.pbs.mm.show.updated")
+            .match_header("ce-subject", "test-child")
+            .match_header("ce-time", "2017-01-01T00:00:00Z")
+            .match_body(json!({ "data": payload.data }).to_string().as_str())
+            .with_status(200)
+            .create();
+
+        let config = show_hooks(vec![hook]);
+
+        let emit = HttpEmitter::new(&payload, &config);
+
+        assert_eq!(emit.update().results(), (1, 0));
+        m.assert();
+    }
 }
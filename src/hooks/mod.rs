@@ -1,7 +1,28 @@
+mod breaker;
+mod client;
+mod cloudevents;
+mod dispatch;
 mod emitter;
+mod exec;
+mod file;
+mod filter;
 mod http;
+mod outbox;
 mod payload;
+mod pool;
+mod projection;
+mod retry;
+#[cfg(test)]
+pub mod test;
 
+pub use hooks::breaker::CircuitBreakers;
+pub use hooks::client::{proxy_for, HookClient};
+pub use hooks::cloudevents::CloudEvent;
+pub use hooks::dispatch::HookEmitter;
 pub use hooks::emitter::{EmitAction, EmitResponse, Emitter};
+pub use hooks::exec::ExecEmitter;
+pub use hooks::file::FileEmitter;
 pub use hooks::http::HttpEmitter;
+pub use hooks::outbox::{Outbox, OutboxEntry};
 pub use hooks::payload::Payload;
+pub use hooks::pool::{DeliveryPool, Submission};
@@ -0,0 +1,412 @@
+extern crate chrono;
+
+use self::chrono::offset::Utc;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use config::{Hook, HookConfig};
+use hooks::{EmitAction, EmitResponse, Emitter, HookClient, HookEmitter, Payload};
+use objects::Object;
+use storage::{Queue, Storage};
+
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OutboxEntry {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub target: String,
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub action: EmitAction,
+    pub payload: Payload,
+    pub created_at: i64,
+    pub attempts: i64,
+}
+
+impl OutboxEntry {
+    pub fn new(target: String, action: EmitAction, payload: Payload) -> OutboxEntry {
+        let now = Utc::now();
+
+        // Ids sort in the order that entries were created so that queues can be replayed in order
+        let id = format!(
+            "{:020}-{:010}",
+            now.timestamp_nanos(),
+            SEQUENCE.fetch_add(1, Ordering::SeqCst)
+        );
+
+        let object_type = payload
+            .data
+            .get("type")
+            .and_then(|type_json| type_json.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        OutboxEntry {
+            id,
+            target,
+            object_type,
+            action,
+            payload,
+            created_at: now.timestamp(),
+            attempts: 0,
+        }
+    }
+
+    fn hook<'a>(&self, config: &'a HookConfig) -> Option<&'a Hook> {
+        config.get(self.object_type.as_str()).and_then(|hooks| {
+            hooks
+                .iter()
+                .find(|hook| hook.target() == Some(&self.target))
+        })
+    }
+}
+
+// Persists hook deliveries in storage. Every delivery is written to the outbox before it is sent
+// so that it survives a crash, and is moved to the dead letter queue if it ultimately fails.
+#[derive(Clone, Copy)]
+pub struct Outbox<'a> {
+    store: &'a dyn Storage<Object>,
+    client: Option<&'a HookClient>,
+}
+
+impl<'a> Outbox<'a> {
+    pub fn new(store: &'a dyn Storage<Object>) -> Outbox<'a> {
+        Outbox {
+            store,
+            client: None,
+        }
+    }
+
+    // Redelivers entries through the given client instead of the process wide default
+    pub fn with_client(mut self, client: &'a HookClient) -> Outbox<'a> {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn deliver<'x, 'y, E: Emitter<'x, 'y>>(
+        &self,
+        emitter: &E,
+        hook: &Hook,
+        action: EmitAction,
+    ) -> Option<(String, bool, i64)> {
+        self.record(hook, action, emitter.payload()).and_then(|entry| {
+            emitter
+                .emit_hook(hook, &action)
+                .map(|(target, delivered, attempts)| {
+                    self.settle(entry, delivered, attempts);
+                    (target, delivered, attempts)
+                })
+        })
+    }
+
+    // Writes a delivery to the outbox before it is sent. The returned entry is settled once the
+    // delivery completes.
+    pub fn record(
+        &self,
+        hook: &Hook,
+        action: EmitAction,
+        payload: &Payload,
+    ) -> Option<OutboxEntry> {
+        hook.target().map(|target| {
+            let entry = OutboxEntry::new(target.clone(), action, payload.clone());
+
+            if let Err(err) = self.store.enqueue(Queue::Outbox, &entry) {
+                error!("Failed to write delivery to {} to the outbox due to {}", target, err);
+            }
+
+            entry
+        })
+    }
+
+    // Delivers entries left in the outbox by a previous process that stopped before they completed
+    pub fn drain(&self, config: &HookConfig) -> EmitResponse {
+        let pending = self.store.queued(Queue::Outbox).unwrap_or_else(|err| {
+            error!("Failed to read pending deliveries from the outbox due to {}", err);
+            vec![]
+        });
+
+        if !pending.is_empty() {
+            info!("Draining {} pending deliveries from the outbox", pending.len());
+        }
+
+        pending
+            .into_iter()
+            .fold(EmitResponse::default(), |response, entry| {
+                match self.redeliver(config, entry) {
+                    Some((hook, delivered, attempts)) => {
+                        response.record(hook, delivered, attempts)
+                    }
+                    None => response,
+                }
+            })
+    }
+
+    // Redelivers dead letters, optionally limited to a single hook target. Entries are sent in the
+    // order that they were originally emitted, and a failure stops any further replay to that hook
+    // so that it does not receive notifications out of order.
+    pub fn replay(&self, config: &HookConfig, target: Option<&str>) -> EmitResponse {
+        let dead_letters = self.store.queued(Queue::DeadLetter).unwrap_or_else(|err| {
+            error!("Failed to read the dead letter queue due to {}", err);
+            vec![]
+        });
+
+        let mut halted = HashSet::new();
+
+        dead_letters
+            .into_iter()
+            .filter(|entry| target.map_or(true, |target| entry.target == target))
+            .fold(EmitResponse::default(), |response, entry| {
+                if halted.contains(&entry.target) {
+                    return response;
+                }
+
+                let entry_target = entry.target.clone();
+
+                if let Err(err) = self.store
+                    .dequeue(Queue::DeadLetter, entry.id.as_str())
+                    .and_then(|_| self.store.enqueue(Queue::Outbox, &entry))
+                {
+                    error!(
+                        "Failed to move dead letter {} to the outbox due to {}",
+                        entry.id, err
+                    );
+                    halted.insert(entry_target);
+                    return response;
+                }
+
+                match self.redeliver(config, entry) {
+                    Some((hook, delivered, attempts)) => {
+                        if !delivered {
+                            halted.insert(entry_target);
+                        }
+
+                        response.record(hook, delivered, attempts)
+                    }
+                    None => {
+                        halted.insert(entry_target);
+                        response
+                    }
+                }
+            })
+    }
+
+    fn redeliver(&self, config: &HookConfig, entry: OutboxEntry) -> Option<(String, bool, i64)> {
+        let emitter = HookEmitter::new(&entry.payload, config);
+
+        let emitter = match self.client {
+            Some(client) => emitter.with_client(client),
+            None => emitter,
+        };
+
+        match entry.hook(config) {
+            Some(hook) => emitter
+                .emit_hook(hook, &entry.action)
+                .map(|(target, delivered, attempts)| {
+                    self.settle(entry.clone(), delivered, attempts);
+                    (target, delivered, attempts)
+                }),
+            None => {
+                warn!(
+                    "Hook {} is no longer configured for {}. Moving delivery {} to the dead \
+                     letter queue.",
+                    entry.target, entry.object_type, entry.id
+                );
+                self.bury(entry, 0);
+                None
+            }
+        }
+    }
+
+    // Removes a completed delivery from the outbox, moving it to the dead letter queue if it
+    // failed. Deliveries that were deferred without an attempt stay in the outbox until it is
+    // next drained.
+    pub fn settle(&self, entry: OutboxEntry, delivered: bool, attempts: i64) {
+        if delivered {
+            self.remove(&entry);
+        } else if attempts > 0 {
+            self.bury(entry, attempts);
+        }
+    }
+
+    fn remove(&self, entry: &OutboxEntry) {
+        if let Err(err) = self.store.dequeue(Queue::Outbox, entry.id.as_str()) {
+            error!("Failed to remove delivery {} from the outbox due to {}", entry.id, err);
+        }
+    }
+
+    fn bury(&self, mut entry: OutboxEntry, attempts: i64) {
+        self.remove(&entry);
+        entry.attempts += attempts;
+
+        if let Err(err) = self.store.enqueue(Queue::DeadLetter, &entry) {
+            error!(
+                "Failed to move delivery {} to the dead letter queue due to {}",
+                entry.id, err
+            );
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Outbox<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Outbox")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito;
+    use mockito::mock;
+
+    use config::{BreakerConfig, HttpConfig};
+    use hooks::test::{http_hook, show_hooks, test_payload};
+    use hooks::{EmitAction, Emitter, HookClient, HookEmitter, Outbox, OutboxEntry};
+    use storage::{Queue, SinkStore, Storage};
+
+    #[test]
+    fn removes_delivered_entries_from_outbox() {
+        let _m = mock("POST", "/outbox_removes_delivered_test/")
+            .with_status(200)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/outbox_removes_delivered_test/");
+
+        let store = SinkStore::new(None).unwrap();
+        let config = show_hooks(vec![http_hook(endpoint.as_str())]);
+
+        let payload = test_payload();
+        let response = HookEmitter::new(&payload, &config)
+            .with_outbox(Outbox::new(&store))
+            .update();
+
+        assert_eq!(response.results(), (1, 0));
+        assert!(store.queued(Queue::Outbox).unwrap().is_empty());
+        assert!(store.queued(Queue::DeadLetter).unwrap().is_empty());
+    }
+
+    #[test]
+    fn moves_failed_entries_to_dead_letters() {
+        let _m = mock("POST", "/outbox_moves_failed_test/")
+            .with_status(500)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/outbox_moves_failed_test/");
+
+        let store = SinkStore::new(None).unwrap();
+        let config = show_hooks(vec![http_hook(endpoint.as_str())]);
+
+        let payload = test_payload();
+        let response = HookEmitter::new(&payload, &config)
+            .with_outbox(Outbox::new(&store))
+            .update();
+        let dead_letters = store.queued(Queue::DeadLetter).unwrap();
+
+        assert_eq!(response.results(), (0, 1));
+        assert!(store.queued(Queue::Outbox).unwrap().is_empty());
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].target, endpoint);
+        assert_eq!(dead_letters[0].attempts, 1);
+        assert_eq!(dead_letters[0].payload, test_payload());
+    }
+
+    #[test]
+    fn replays_dead_letters_in_order() {
+        let _m = mock("POST", "/outbox_replays_dead_letters_test/")
+            .with_status(200)
+            .expect(2)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/outbox_replays_dead_letters_test/");
+
+        let store = SinkStore::new(None).unwrap();
+        let config = show_hooks(vec![http_hook(endpoint.as_str())]);
+
+        let first = OutboxEntry::new(endpoint.clone(), EmitAction::Update, test_payload());
+        let second = OutboxEntry::new(endpoint.clone(), EmitAction::Update, test_payload());
+        let other = OutboxEntry::new(
+            "http://0.0.0.0/other/".to_string(),
+            EmitAction::Update,
+            test_payload(),
+        );
+
+        store.enqueue(Queue::DeadLetter, &second).unwrap();
+        store.enqueue(Queue::DeadLetter, &other).unwrap();
+        store.enqueue(Queue::DeadLetter, &first).unwrap();
+
+        let response = Outbox::new(&store).replay(&config, Some(endpoint.as_str()));
+
+        assert_eq!(response.success, vec![endpoint.clone(), endpoint.clone()]);
+        assert_eq!(store.queued(Queue::DeadLetter).unwrap(), vec![other]);
+        assert!(store.queued(Queue::Outbox).unwrap().is_empty());
+    }
+
+    #[test]
+    fn drains_pending_outbox_entries() {
+        let _m = mock("DELETE", "/outbox_drains_pending_test/test-child/")
+            .with_status(200)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/outbox_drains_pending_test/");
+
+        let store = SinkStore::new(None).unwrap();
+        let config = show_hooks(vec![http_hook(endpoint.as_str())]);
+
+        let pending = OutboxEntry::new(endpoint.clone(), EmitAction::Delete, test_payload());
+        store.enqueue(Queue::Outbox, &pending).unwrap();
+
+        let response = Outbox::new(&store).drain(&config);
+
+        let mut delete_endpoint = endpoint.clone();
+        delete_endpoint.push_str("test-child/");
+
+        assert_eq!(response.success, vec![delete_endpoint]);
+        assert!(store.queued(Queue::Outbox).unwrap().is_empty());
+        assert!(store.queued(Queue::DeadLetter).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_deferred_entries_in_outbox() {
+        let m = mock("POST", "/outbox_keeps_deferred_test/")
+            .with_status(500)
+            .expect(1)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/outbox_keeps_deferred_test/");
+
+        let client = HookClient::new(&HttpConfig {
+            circuit_breaker: BreakerConfig {
+                failures: 1,
+                cooldown: 60000,
+            },
+            ..HttpConfig::default()
+        }).unwrap();
+
+        let store = SinkStore::new(None).unwrap();
+        let config = show_hooks(vec![http_hook(endpoint.as_str())]);
+
+        let payload = test_payload();
+        let emitter = HookEmitter::new(&payload, &config)
+            .with_outbox(Outbox::new(&store))
+            .with_client(&client);
+
+        assert_eq!(emitter.update().results(), (0, 1));
+        assert_eq!(emitter.update().results(), (0, 1));
+
+        assert_eq!(store.queued(Queue::DeadLetter).unwrap().len(), 1);
+        assert_eq!(store.queued(Queue::Outbox).unwrap().len(), 1);
+
+        let response = Outbox::new(&store).with_client(&client).drain(&config);
+
+        assert_eq!(response.attempts, 0);
+        assert_eq!(store.queued(Queue::Outbox).unwrap().len(), 1);
+        m.assert();
+    }
+}
@@ -4,19 +4,27 @@ extern crate serde_json;
 use serde_json::Map;
 use serde_json::Value as Json;
 
-use hooks::Emitter;
-use config::HookConfig;
-use objects::{Object, Ref};
+use std::collections::BTreeSet;
+
+use hooks::{filter, projection, CloudEvent, EmitAction, Emitter};
+use config::{Hook, HookConfig, PayloadFormat};
+use objects::{utils, Object, Ref};
+use storage::Tombstone;
 use types::StorageEngine;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Payload {
     pub data: Map<String, Json>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Map<String, Json>>,
 }
 
 impl Payload {
     pub fn new(data: Map<String, Json>) -> Payload {
-        Payload { data: data }
+        Payload {
+            data: data,
+            changes: None,
+        }
     }
 
     pub fn from_ref(refr: &Ref) -> Payload {
@@ -26,6 +34,30 @@ impl Payload {
         Payload::new(data)
     }
 
+    // The last known state of a deleted object and its parents, marked as deleted. Objects that
+    // were never cached are described by their id and type alone. Both carry the reason for the
+    // deletion and the changelog entry that listed it, if there was one.
+    pub fn from_deleted<T: StorageEngine>(refr: &Ref, tombstone: &Tombstone, store: &T) -> Payload {
+        let mut payload = store
+            .get(refr.id.as_str(), refr.ref_type.as_str())
+            .and_then(|res| res.ok())
+            .and_then(|object| Payload::from_object(&object, store))
+            .map(|mut payload| {
+                payload.data.insert("deleted".to_string(), Json::Bool(true));
+                payload
+            })
+            .unwrap_or_else(|| Payload::from_ref(refr));
+
+        let mut deletion = json!({ "reason": tombstone.reason });
+
+        if let Some(ref changelog) = tombstone.changelog {
+            deletion["changelog"] = json!(changelog);
+        }
+
+        payload.data.insert("deletion".to_string(), deletion);
+        payload
+    }
+
     pub fn from_object<T: StorageEngine>(object: &Object, store: &T) -> Option<Payload> {
         if let Json::Object(mut data) = object.attributes.clone() {
             data.insert("id".to_string(), Json::String(object.id.clone()));
@@ -50,6 +82,73 @@ impl Payload {
         }
     }
 
+    pub fn with_changes(mut self, previous: &Object, current: &Object) -> Payload {
+        let empty = Map::new();
+        let old_attrs = previous.attributes.as_object().unwrap_or(&empty);
+        let new_attrs = current.attributes.as_object().unwrap_or(&empty);
+
+        let changes = old_attrs
+            .keys()
+            .chain(new_attrs.keys())
+            .collect::<BTreeSet<&String>>()
+            .into_iter()
+            .filter_map(|key| {
+                let old = old_attrs.get(key).cloned().unwrap_or(Json::Null);
+                let new = new_attrs.get(key).cloned().unwrap_or(Json::Null);
+
+                if utils::normalize_json_dates(old.clone()) != utils::normalize_json_dates(new.clone())
+                {
+                    Some((key.clone(), json!({ "old": old, "new": new })))
+                } else {
+                    None
+                }
+            })
+            .collect::<Map<String, Json>>();
+
+        self.changes = Some(changes);
+        self
+    }
+
+    pub fn object_type(&self) -> &str {
+        self.data
+            .get("type")
+            .and_then(|type_json| type_json.as_str())
+            .unwrap_or("")
+    }
+
+    // Whether the filter of the hook lets the payload through
+    pub fn matches(&self, hook: &Hook) -> bool {
+        filter::matches(&hook.filter, self)
+    }
+
+    pub fn render(&self, hook: &Hook) -> Json {
+        let mut body = Map::new();
+        body.insert(
+            "data".to_string(),
+            projection::project(&hook.projection, &self.data),
+        );
+
+        if hook.include_changes {
+            if let Some(ref changes) = self.changes {
+                body.insert("changes".to_string(), Json::Object(changes.clone()));
+            }
+        }
+
+        Json::Object(body)
+    }
+
+    // The document that a hook receives for the action in its payload format
+    pub fn body(&self, hook: &Hook, action: &EmitAction) -> Json {
+        match hook.format {
+            PayloadFormat::Default => {
+                let mut body = self.render(hook);
+                body["action"] = Json::String(action.event().to_string());
+                body
+            }
+            PayloadFormat::CloudEvents => CloudEvent::new(hook, self, action).structured(),
+        }
+    }
+
     pub fn emitter<'a, 'b, T: Emitter<'a, 'b>, F>(&'a self, config: &'b HookConfig, con: F) -> T
     where
         F: FnOnce(&'a Payload, &'b HookConfig) -> T,
@@ -65,9 +164,10 @@ mod tests {
 
     use std::collections::BTreeMap;
 
+    use config::{DBConfig, DBEngine};
     use hooks::{Emitter, HttpEmitter, Payload};
     use objects::{Importable, Object, Ref};
-    use storage::SinkStore;
+    use storage::{ChangelogEntry, RemovalReason, SinkStore, SqliteStore, Storage, Tombstone};
 
     #[test]
     fn payload_from_ref() {
@@ -89,6 +189,109 @@ mod tests {
         )
     }
 
+    #[test]
+    fn payload_from_deleted_ref_without_cache() {
+        let refr = Ref::new(
+            "payload-test-id".to_string(),
+            Json::Object(Map::new()),
+            "asset".to_string(),
+            "http://0.0.0.0".to_string(),
+        );
+        let store = SinkStore::new(None).unwrap();
+        let tombstone = Tombstone::new(
+            "payload-test-id".to_string(),
+            "asset".to_string(),
+            RemovalReason::NotAuthorized,
+        );
+
+        let mut expected = Payload::from_ref(&refr);
+        expected
+            .data
+            .insert("deletion".to_string(), json!({ "reason": "not_authorized" }));
+
+        assert_eq!(Payload::from_deleted(&refr, &tombstone, &store), expected);
+    }
+
+    #[test]
+    fn payload_from_deleted_ref_with_cache() {
+        let store = SqliteStore::new(&DBConfig {
+            engine: DBEngine::Sqlite,
+            host: "".to_string(),
+            port: 0,
+            name: "".to_string(),
+            username: "".to_string(),
+            password: "".to_string(),
+            path: Some(":memory:".to_string()),
+        }).unwrap();
+
+        let parent = Object::new(
+            "test-parent".to_string(),
+            json!({ "title": "Test Show" }),
+            "show".to_string(),
+            "http://0.0.0.0/parent".to_string(),
+        );
+
+        let child = Object::new(
+            "test-child".to_string(),
+            json!({
+                "title": "Test Asset",
+                "parent_tree": {
+                    "id": "test-parent",
+                    "attributes": {},
+                    "type": "show",
+                    "links": {
+                        "self": "http://0.0.0.0/parent"
+                    }
+                }
+            }),
+            "asset".to_string(),
+            "http://0.0.0.0/child".to_string(),
+        );
+
+        store.put(&parent).unwrap();
+        store.put(&child).unwrap();
+
+        let refr = Ref::new(
+            "test-child".to_string(),
+            json!({ "action": "delete" }),
+            "asset".to_string(),
+            "".to_string(),
+        );
+
+        let tombstone = Tombstone::new(
+            "test-child".to_string(),
+            "asset".to_string(),
+            RemovalReason::Changelog,
+        ).with_changelog(Some(ChangelogEntry {
+            action: "delete".to_string(),
+            timestamp: "2017-02-21T20:42:27.010750Z".to_string(),
+        }));
+
+        let payload = Payload::from_deleted(&refr, &tombstone, &store);
+
+        assert_eq!(payload.data["deleted"], Json::Bool(true));
+        assert_eq!(
+            payload.data["deletion"],
+            json!({
+                "reason": "changelog",
+                "changelog": {
+                    "action": "delete",
+                    "timestamp": "2017-02-21T20:42:27.010750Z"
+                }
+            })
+        );
+        assert_eq!(payload.data["title"], json!("Test Asset"));
+        assert_eq!(
+            payload.data["parent"],
+            json!({
+                "id": "test-parent",
+                "type": "show",
+                "title": "Test Show",
+                "parent": null
+            })
+        );
+    }
+
     #[test]
     fn invalid_object_attributes() {
         let obj_id = "obj-test-id".to_string();
@@ -191,7 +394,7 @@ mod tests {
                 "parent": null
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
 
             let test_payload = Payload::from_object(&obj, &store).unwrap();
 
@@ -216,14 +419,65 @@ mod tests {
                 "type": "franchise"
             }
         }) {
-            let payload = Payload { data: payload_map };
+            let payload = Payload::new(payload_map);
             let emit = HttpEmitter::new(&payload, &config);
 
             let test_emit = payload.emitter(&config, HttpEmitter::new);
 
-            assert_eq!(emit, test_emit);
+            assert_eq!(emit.payload(), test_emit.payload());
+            assert_eq!(emit.config(), test_emit.config());
         } else {
             panic!("Failed to create payload map");
         }
     }
+
+    #[test]
+    fn payload_with_changes() {
+        let previous = Object::new(
+            "obj-test-id".to_string(),
+            json!({
+                "title": "Old Title",
+                "description": "Unchanged",
+                "premiered_on": "2017-01-01",
+                "updated_at": "2017-01-01T00:00:00.123Z"
+            }),
+            "asset".to_string(),
+            "http://0.0.0.0/obj-test-id/".to_string(),
+        );
+
+        let current = Object::new(
+            "obj-test-id".to_string(),
+            json!({
+                "title": "New Title",
+                "description": "Unchanged",
+                "duration": 60,
+                "updated_at": "2017-01-01T00:00:00.123456Z"
+            }),
+            "asset".to_string(),
+            "http://0.0.0.0/obj-test-id/".to_string(),
+        );
+
+        let store = SinkStore::new(None).unwrap();
+
+        let payload = Payload::from_object(&current, &store)
+            .unwrap()
+            .with_changes(&previous, &current);
+
+        let changes = json!({
+            "duration": {
+                "old": null,
+                "new": 60
+            },
+            "premiered_on": {
+                "old": "2017-01-01",
+                "new": null
+            },
+            "title": {
+                "old": "Old Title",
+                "new": "New Title"
+            }
+        });
+
+        assert_eq!(Json::Object(payload.changes.unwrap()), changes);
+    }
 }
@@ -0,0 +1,658 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config::{DeliveryConfig, Hook, HookConfig};
+use hooks::{EmitAction, Emitter, HookClient, HookEmitter, Outbox, OutboxEntry, Payload};
+
+struct Job {
+    id: u64,
+    hook: Hook,
+    action: EmitAction,
+    payload: Payload,
+    entry: Option<OutboxEntry>,
+}
+
+// A delivery handed off to the pool, which can be waited on without waiting on the whole pool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Submission(u64);
+
+struct Delivery {
+    entry: Option<OutboxEntry>,
+    result: Option<(String, bool, i64)>,
+}
+
+// Deliveries waiting for a single hook target along with the limits that apply to it
+struct Endpoint {
+    jobs: VecDeque<Job>,
+    active: usize,
+    concurrency: usize,
+    interval: Option<Duration>,
+    next_start: Instant,
+}
+
+impl Endpoint {
+    fn new(concurrency: usize, interval: Option<Duration>) -> Endpoint {
+        Endpoint {
+            jobs: VecDeque::new(),
+            active: 0,
+            concurrency,
+            interval,
+            next_start: Instant::now(),
+        }
+    }
+
+    fn ready_at(&self) -> Option<Instant> {
+        if self.jobs.is_empty() || self.active >= self.concurrency {
+            None
+        } else {
+            Some(self.next_start)
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    endpoints: HashMap<String, Endpoint>,
+    pending: usize,
+    unfinished: HashSet<u64>,
+    submitted: u64,
+    delivered: i64,
+    undelivered: i64,
+    unsettled: Vec<(OutboxEntry, bool, i64)>,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    hooks: HookConfig,
+    client: HookClient,
+}
+
+impl State {
+    // Starts the first delivery that its target allows at the given time. Otherwise returns the
+    // time at which one will be allowed, if there is a delivery that is only held by a rate limit.
+    fn take(&mut self, now: Instant) -> Result<(String, Job), Option<Instant>> {
+        let mut wake_at: Option<Instant> = None;
+
+        for (target, endpoint) in &mut self.endpoints {
+            match endpoint.ready_at() {
+                Some(at) if at <= now => {
+                    if let Some(job) = endpoint.jobs.pop_front() {
+                        endpoint.active += 1;
+
+                        if let Some(interval) = endpoint.interval {
+                            endpoint.next_start = cmp::max(endpoint.next_start, now) + interval;
+                        }
+
+                        return Ok((target.clone(), job));
+                    }
+                }
+                Some(at) => wake_at = Some(wake_at.map_or(at, |wake| cmp::min(wake, at))),
+                None => (),
+            }
+        }
+
+        Err(wake_at)
+    }
+}
+
+impl Shared {
+    fn new(hooks: &HookConfig, client: &HookClient) -> Shared {
+        Shared {
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+            hooks: hooks.clone(),
+            client: client.clone(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Adds a delivery to the queue of its target, blocking while that queue is full
+    fn enqueue(&self, target: String, endpoint: Endpoint, mut job: Job, capacity: usize) -> u64 {
+        let mut state = self.lock();
+
+        while !state.shutdown
+            && state
+                .endpoints
+                .get(&target)
+                .map_or(false, |endpoint| endpoint.jobs.len() >= capacity)
+        {
+            state = self.changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+
+        state.submitted += 1;
+        job.id = state.submitted;
+
+        let id = job.id;
+
+        state
+            .endpoints
+            .entry(target)
+            .or_insert(endpoint)
+            .jobs
+            .push_back(job);
+
+        state.pending += 1;
+        state.unfinished.insert(id);
+
+        self.changed.notify_all();
+
+        id
+    }
+
+    // Blocks until a delivery can be started without exceeding the limits of its target
+    fn next(&self) -> Option<(String, Job)> {
+        let mut state = self.lock();
+
+        loop {
+            if state.shutdown {
+                return None;
+            }
+
+            let now = Instant::now();
+
+            state = match state.take(now) {
+                Ok(started) => {
+                    // Wakes any submission that is waiting for room in the queue
+                    self.changed.notify_all();
+                    return Some(started);
+                }
+                Err(Some(at)) => {
+                    self.changed
+                        .wait_timeout(state, at - now)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+                Err(None) => self.changed
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+
+    fn complete(&self, target: &str, id: u64, delivery: Delivery) {
+        let mut state = self.lock();
+
+        if let Some(endpoint) = state.endpoints.get_mut(target) {
+            endpoint.active -= 1;
+        }
+
+        state.pending -= 1;
+        state.unfinished.remove(&id);
+
+        let (delivered, attempts) = match delivery.result {
+            Some((_, true, attempts)) => {
+                state.delivered += 1;
+                (true, attempts)
+            }
+            Some((_, false, attempts)) => {
+                state.undelivered += 1;
+                (false, attempts)
+            }
+            None => (false, 0),
+        };
+
+        if let Some(entry) = delivery.entry {
+            state.unsettled.push((entry, delivered, attempts));
+        }
+
+        self.changed.notify_all();
+    }
+}
+
+fn work(shared: Arc<Shared>) {
+    while let Some((target, job)) = shared.next() {
+        let result = HookEmitter::new(&job.payload, &shared.hooks)
+            .with_client(&shared.client)
+            .emit_hook(&job.hook, &job.action);
+
+        shared.complete(
+            target.as_str(),
+            job.id,
+            Delivery {
+                entry: job.entry,
+                result,
+            },
+        );
+    }
+}
+
+// Collects the limits that the configured hooks set for each of their targets, so that they do not
+// depend on which hook happens to deliver to a target first
+fn target_limits(hooks: &HookConfig) -> HashMap<String, (Option<usize>, Option<f64>)> {
+    let mut limits = HashMap::new();
+
+    for hook in hooks.values().flat_map(|hooks| hooks.iter()) {
+        if let Some(target) = hook.target() {
+            let limit = limits.entry(target.clone()).or_insert((None, None));
+            limit.0 = limit.0.or(hook.concurrency);
+            limit.1 = limit.1.or(hook.rate_limit);
+        }
+    }
+
+    limits
+}
+
+// Delivers notifications on dedicated worker threads so that imports do not wait on slow hooks.
+// Each hook target has its own queue that is limited to a number of concurrent deliveries and,
+// optionally, a number of deliveries started per second. Submissions block while the queue of
+// their target is full.
+pub struct DeliveryPool {
+    shared: Arc<Shared>,
+    config: DeliveryConfig,
+    limits: HashMap<String, (Option<usize>, Option<f64>)>,
+}
+
+impl DeliveryPool {
+    pub fn new(config: &DeliveryConfig, hooks: &HookConfig, client: &HookClient) -> DeliveryPool {
+        let shared = Arc::new(Shared::new(hooks, client));
+
+        for _ in 0..cmp::max(config.workers, 1) {
+            let shared = shared.clone();
+            thread::spawn(move || work(shared));
+        }
+
+        DeliveryPool {
+            shared,
+            config: config.clone(),
+            limits: target_limits(hooks),
+        }
+    }
+
+    // Queues a delivery to a hook. The outbox entry for the delivery, if there is one, is settled
+    // once the delivery has completed and the pool is next settled or waited on. Nothing is
+    // queued for a hook without a target.
+    pub fn submit(
+        &self,
+        hook: &Hook,
+        action: EmitAction,
+        payload: &Payload,
+        entry: Option<OutboxEntry>,
+    ) -> Option<Submission> {
+        let target = match hook.target() {
+            Some(target) => target.clone(),
+            None => return None,
+        };
+
+        let (concurrency, rate_limit) = self.limits
+            .get(&target)
+            .cloned()
+            .unwrap_or((hook.concurrency, hook.rate_limit));
+
+        let concurrency = cmp::max(concurrency.unwrap_or(self.config.concurrency), 1);
+        let interval = rate_limit
+            .or(self.config.rate_limit)
+            .and_then(|rate| {
+                if rate > 0.0 {
+                    Some(Duration::from_secs_f64(1.0 / rate))
+                } else {
+                    None
+                }
+            });
+
+        let id = self.shared.enqueue(
+            target,
+            Endpoint::new(concurrency, interval),
+            Job {
+                id: 0,
+                hook: hook.clone(),
+                action,
+                payload: payload.clone(),
+                entry,
+            },
+            cmp::max(self.config.queue_size, 1),
+        );
+
+        Some(Submission(id))
+    }
+
+    // Blocks until the given deliveries have completed. Other deliveries are not waited on and
+    // nothing is settled, so their results are still collected by waiting on the pool.
+    pub fn wait_for(&self, submissions: &[Submission]) {
+        let mut state = self.shared.lock();
+
+        while !state.shutdown
+            && submissions
+                .iter()
+                .any(|submission| state.unfinished.contains(&submission.0))
+        {
+            state = self.shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    // Settles the outbox entries of the deliveries that have completed since it was last called
+    pub fn settle(&self, outbox: Outbox) {
+        let unsettled = mem::take(&mut self.shared.lock().unsettled);
+
+        for (entry, delivered, attempts) in unsettled {
+            outbox.settle(entry, delivered, attempts);
+        }
+    }
+
+    // Blocks until every submitted delivery has completed and settles the rest of them in the
+    // outbox. Returns the number of deliveries that succeeded and failed since it was last called.
+    pub fn wait(&self, outbox: Option<Outbox>) -> (i64, i64) {
+        let mut state = self.shared.lock();
+
+        while state.pending > 0 {
+            state = self.shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+
+        let results = (
+            mem::replace(&mut state.delivered, 0),
+            mem::replace(&mut state.undelivered, 0),
+        );
+        drop(state);
+
+        if let Some(outbox) = outbox {
+            self.settle(outbox);
+        }
+
+        results
+    }
+}
+
+impl fmt::Debug for DeliveryPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DeliveryPool {{ config: {:?} }}", self.config)
+    }
+}
+
+impl Drop for DeliveryPool {
+    // Deliveries that have not started are abandoned. Their outbox entries are delivered when the
+    // outbox is next drained.
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito;
+    use mockito::mock;
+
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use config::{DeliveryConfig, Hook, HookKind};
+    use hooks::test::{http_hook, shell_hook, show_hooks, test_dir, test_payload};
+    use hooks::{EmitAction, HookClient, Outbox};
+    use storage::{Queue, SinkStore, Storage};
+
+    use super::{Delivery, DeliveryPool, Endpoint, Job, Shared};
+
+    // Runs a shell script with the given directory as $0
+    fn gated_hook(script: &str, dir: &Path) -> Hook {
+        let mut hook = shell_hook(script);
+        hook.args.push(dir.to_string_lossy().into_owned());
+        hook
+    }
+
+    fn test_shared() -> Shared {
+        Shared::new(&BTreeMap::new(), HookClient::shared())
+    }
+
+    fn enqueue_all(shared: &Shared, endpoint: Endpoint, count: usize) {
+        let hook = Hook {
+            kind: HookKind::Exec,
+            command: Some("sleep".to_string()),
+            ..Hook::default()
+        };
+
+        for _ in 0..count {
+            let endpoint = Endpoint::new(endpoint.concurrency, endpoint.interval);
+            let job = Job {
+                id: 0,
+                hook: hook.clone(),
+                action: EmitAction::Update,
+                payload: test_payload(),
+                entry: None,
+            };
+
+            shared.enqueue("sleep".to_string(), endpoint, job, count);
+        }
+    }
+
+    fn submit_all(pool: &DeliveryPool, hook: &Hook, count: usize) {
+        for _ in 0..count {
+            pool.submit(hook, EmitAction::Update, &test_payload(), None);
+        }
+    }
+
+    #[test]
+    fn delivers_and_settles_submitted_notifications() {
+        let m = mock("POST", "/pool_delivers_test/")
+            .with_status(200)
+            .expect(2)
+            .create();
+
+        let mut endpoint = mockito::SERVER_URL.to_string();
+        endpoint.push_str("/pool_delivers_test/");
+
+        let hook = http_hook(endpoint.as_str());
+        let config = show_hooks(vec![hook.clone()]);
+
+        let store = SinkStore::new(None).unwrap();
+        let outbox = Outbox::new(&store);
+        let pool = DeliveryPool::new(
+            &DeliveryConfig::default(),
+            &config,
+            HookClient::shared(),
+        );
+
+        let payload = test_payload();
+
+        for _ in 0..2 {
+            let entry = outbox.record(&hook, EmitAction::Update, &payload);
+            pool.submit(&hook, EmitAction::Update, &payload, entry);
+        }
+
+        assert_eq!(pool.wait(Some(outbox)), (2, 0));
+        assert!(store.queued(Queue::Outbox).unwrap().is_empty());
+        assert!(store.queued(Queue::DeadLetter).unwrap().is_empty());
+        m.assert();
+    }
+
+    #[test]
+    fn settles_completed_deliveries_before_the_pool_is_waited_on() {
+        let hook = shell_hook("exit 0");
+        let config = show_hooks(vec![hook.clone()]);
+
+        let store = SinkStore::new(None).unwrap();
+        let outbox = Outbox::new(&store);
+        let pool = DeliveryPool::new(
+            &DeliveryConfig::default(),
+            &config,
+            HookClient::shared(),
+        );
+
+        let payload = test_payload();
+        let entry = outbox.record(&hook, EmitAction::Update, &payload);
+        let submission = pool.submit(&hook, EmitAction::Update, &payload, entry);
+
+        pool.wait_for(&[submission.unwrap()]);
+        assert_eq!(store.queued(Queue::Outbox).unwrap().len(), 1);
+
+        pool.settle(outbox);
+        assert!(store.queued(Queue::Outbox).unwrap().is_empty());
+        assert!(pool.shared.lock().unsettled.is_empty());
+
+        assert_eq!(pool.wait(None), (1, 0));
+    }
+
+    #[test]
+    fn runs_deliveries_to_a_target_concurrently() {
+        let dir = test_dir("pool_concurrent_test");
+        let pool = DeliveryPool::new(
+            &DeliveryConfig::default(),
+            &BTreeMap::new(),
+            HookClient::shared(),
+        );
+
+        // Each delivery only succeeds once all four of them have started
+        let hook = gated_hook(
+            "touch \"$0/$$\"; i=0; \
+             while [ \"$(ls \"$0\" | wc -l)\" -lt 4 ]; do \
+               i=$((i + 1)); [ \"$i\" -gt 3000 ] && exit 1; sleep 0.01; \
+             done",
+            &dir,
+        );
+
+        submit_all(&pool, &hook, 4);
+
+        assert_eq!(pool.wait(None), (4, 0));
+    }
+
+    #[test]
+    fn waits_only_for_the_given_deliveries() {
+        let dir = test_dir("pool_wait_for_test");
+        let pool = DeliveryPool::new(
+            &DeliveryConfig::default(),
+            &BTreeMap::new(),
+            HookClient::shared(),
+        );
+
+        let gated = gated_hook(
+            "i=0; while [ ! -e \"$0/open\" ]; do \
+               i=$((i + 1)); [ \"$i\" -gt 3000 ] && exit 1; sleep 0.01; \
+             done",
+            &dir,
+        );
+
+        pool.submit(&gated, EmitAction::Update, &test_payload(), None);
+
+        let quick = shell_hook("exit 0");
+        let submission = pool.submit(&quick, EmitAction::Update, &test_payload(), None);
+        pool.wait_for(&[submission.unwrap()]);
+
+        assert_eq!(pool.shared.lock().pending, 1);
+
+        fs::write(dir.join("open"), "").unwrap();
+
+        assert_eq!(pool.wait(None), (2, 0));
+    }
+
+    #[test]
+    fn limits_concurrency_per_target() {
+        let shared = test_shared();
+        enqueue_all(&shared, Endpoint::new(2, None), 3);
+
+        let now = Instant::now();
+        let mut state = shared.lock();
+
+        assert!(state.take(now).is_ok());
+        assert!(state.take(now).is_ok());
+        assert_eq!(state.take(now).err(), Some(None));
+
+        drop(state);
+        shared.complete("sleep", 1, Delivery { entry: None, result: None });
+
+        assert!(shared.lock().take(now).is_ok());
+    }
+
+    #[test]
+    fn limits_deliveries_per_second() {
+        let shared = test_shared();
+        let interval = Duration::from_millis(100);
+        enqueue_all(&shared, Endpoint::new(4, Some(interval)), 3);
+
+        let now = Instant::now();
+        let mut state = shared.lock();
+
+        assert!(state.take(now).is_ok());
+        assert_eq!(state.take(now).err(), Some(Some(now + interval)));
+        assert!(state.take(now + interval).is_ok());
+        assert_eq!(
+            state.take(now + interval).err(),
+            Some(Some(now + interval * 2))
+        );
+    }
+
+    #[test]
+    fn applies_the_configured_limits_of_a_target_to_each_of_its_hooks() {
+        let limited = Hook {
+            concurrency: Some(1),
+            rate_limit: Some(5.0),
+            ..shell_hook("exit 0")
+        };
+        let config = show_hooks(vec![shell_hook("exit 1"), limited]);
+
+        let pool = DeliveryPool::new(
+            &DeliveryConfig::default(),
+            &config,
+            HookClient::shared(),
+        );
+
+        pool.submit(&config["show"][0], EmitAction::Update, &test_payload(), None);
+        pool.wait(None);
+
+        let state = pool.shared.lock();
+        let endpoint = &state.endpoints["sh"];
+
+        assert_eq!(endpoint.concurrency, 1);
+        assert_eq!(endpoint.interval, Some(Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn blocks_submissions_while_a_queue_is_full() {
+        let dir = test_dir("pool_queue_full_test");
+        let pool = Arc::new(DeliveryPool::new(
+            &DeliveryConfig {
+                workers: 1,
+                queue_size: 1,
+                ..DeliveryConfig::default()
+            },
+            &BTreeMap::new(),
+            HookClient::shared(),
+        ));
+
+        // Deliveries wait until the gate is opened, so the queue can only drain after that
+        let hook = Hook {
+            concurrency: Some(1),
+            ..gated_hook(
+                "i=0; while [ ! -e \"$0/open\" ]; do \
+                   i=$((i + 1)); [ \"$i\" -gt 3000 ] && exit 1; sleep 0.01; \
+                 done",
+                &dir,
+            )
+        };
+
+        let (submitted, done) = mpsc::channel();
+        let submitter = pool.clone();
+
+        let submissions = thread::spawn(move || {
+            submit_all(&submitter, &hook, 3);
+            submitted.send(()).unwrap();
+        });
+
+        assert!(done.recv_timeout(Duration::from_millis(200)).is_err());
+
+        fs::write(dir.join("open"), "").unwrap();
+        done.recv().unwrap();
+        submissions.join().unwrap();
+
+        assert_eq!(pool.wait(None), (3, 0));
+    }
+}
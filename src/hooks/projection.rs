@@ -0,0 +1,190 @@
+extern crate serde_json;
+
+use serde_json::Map;
+use serde_json::Value as Json;
+
+use config::Projection;
+use objects::utils;
+
+fn truncate_parents(json: &mut Json, depth: usize) {
+    if let Some(parent) = json.get_mut("parent") {
+        if depth == 0 {
+            *parent = Json::Null;
+        } else {
+            truncate_parents(parent, depth - 1);
+        }
+    }
+}
+
+fn select_json_paths(json: &Json, paths: &[String]) -> Json {
+    paths
+        .iter()
+        .fold(Json::Object(Map::new()), |mut selected, path| {
+            let pointer = format!("/{}", path.replace('.', "/"));
+
+            if let Some(value) = json.pointer(pointer.as_str()) {
+                utils::insert_json_path(&mut selected, path, value.clone());
+            }
+
+            selected
+        })
+}
+
+// Builds the data that a hook receives. The parent chain is limited first, then the included
+// paths are selected and the excluded paths removed, and finally the remaining paths are renamed.
+pub fn project(projection: &Projection, data: &Map<String, Json>) -> Json {
+    let mut json = Json::Object(data.clone());
+
+    if let Some(depth) = projection.max_parent_depth {
+        truncate_parents(&mut json, depth);
+    }
+
+    if !projection.include.is_empty() {
+        json = select_json_paths(&json, &projection.include);
+    }
+
+    let mut json = utils::remove_json_paths(json, &projection.exclude);
+
+    for (from, to) in &projection.rename {
+        if let Some(value) = utils::take_json_path(&mut json, from) {
+            utils::insert_json_path(&mut json, to, value);
+        }
+    }
+
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+    use serde_json::Value as Json;
+
+    use std::collections::BTreeMap;
+
+    use config::Projection;
+
+    use super::project;
+
+    fn test_data() -> Map<String, Json> {
+        json!({
+            "id": "test-asset",
+            "type": "asset",
+            "title": "Test Asset",
+            "duration": 60,
+            "parent": {
+                "id": "test-episode",
+                "type": "episode",
+                "title": "Test Episode",
+                "parent": {
+                    "id": "test-season",
+                    "type": "season",
+                    "parent": null
+                }
+            }
+        }).as_object()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[test]
+    fn default_projection_keeps_everything() {
+        let data = test_data();
+
+        assert_eq!(
+            project(&Projection::default(), &data),
+            Json::Object(data)
+        );
+    }
+
+    #[test]
+    fn includes_and_excludes_paths() {
+        let projection = Projection {
+            include: vec![
+                "id".to_string(),
+                "title".to_string(),
+                "parent.id".to_string(),
+                "parent.title".to_string(),
+                "missing.path".to_string(),
+            ],
+            exclude: vec!["parent.title".to_string()],
+            ..Projection::default()
+        };
+
+        assert_eq!(
+            project(&projection, &test_data()),
+            json!({
+                "id": "test-asset",
+                "title": "Test Asset",
+                "parent": {
+                    "id": "test-episode"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn renames_paths() {
+        let mut rename = BTreeMap::new();
+        rename.insert("title".to_string(), "name".to_string());
+        rename.insert("parent.title".to_string(), "episode.name".to_string());
+
+        let projection = Projection {
+            include: vec!["id".to_string(), "title".to_string(), "parent".to_string()],
+            rename,
+            max_parent_depth: Some(0),
+            ..Projection::default()
+        };
+
+        assert_eq!(
+            project(&projection, &test_data()),
+            json!({
+                "id": "test-asset",
+                "name": "Test Asset",
+                "parent": null
+            })
+        );
+
+        let mut rename = BTreeMap::new();
+        rename.insert("parent.title".to_string(), "episode.name".to_string());
+
+        let projection = Projection {
+            include: vec!["id".to_string(), "parent.title".to_string()],
+            rename,
+            ..Projection::default()
+        };
+
+        assert_eq!(
+            project(&projection, &test_data()),
+            json!({
+                "id": "test-asset",
+                "parent": {},
+                "episode": {
+                    "name": "Test Episode"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn limits_parent_depth() {
+        let projection = Projection {
+            max_parent_depth: Some(1),
+            exclude: vec!["title".to_string(), "duration".to_string()],
+            ..Projection::default()
+        };
+
+        assert_eq!(
+            project(&projection, &test_data()),
+            json!({
+                "id": "test-asset",
+                "type": "asset",
+                "parent": {
+                    "id": "test-episode",
+                    "type": "episode",
+                    "title": "Test Episode",
+                    "parent": null
+                }
+            })
+        );
+    }
+}
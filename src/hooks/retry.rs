@@ -0,0 +1,123 @@
+extern crate chrono;
+extern crate rand;
+
+use self::chrono::offset::Utc;
+use self::chrono::DateTime;
+use self::rand::Rng;
+
+use std::cmp;
+use std::time::Duration;
+
+use config::RetryConfig;
+
+pub fn is_retryable_status(policy: &RetryConfig, status: u16) -> bool {
+    policy.retry_statuses.contains(&status)
+}
+
+// Computes how long to wait before the next attempt. Backoff doubles with each attempt starting
+// from the base delay, and a Retry-After duration from the remote takes precedence when it is
+// longer. Neither is allowed to exceed the max delay.
+pub fn delay(policy: &RetryConfig, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    let exponent = cmp::min(attempt.saturating_sub(1), 32);
+    let backoff = policy
+        .base_delay
+        .saturating_mul(2u64.saturating_pow(exponent))
+        .min(policy.max_delay);
+
+    let backoff = if policy.jitter && backoff > 0 {
+        let half = backoff / 2;
+        half + rand::thread_rng().gen_range(0, backoff - half + 1)
+    } else {
+        backoff
+    };
+
+    let wait = retry_after.map_or(backoff, |requested| {
+        cmp::max(backoff, requested.as_secs() * 1000 + requested.subsec_millis() as u64)
+    });
+
+    Duration::from_millis(cmp::min(wait, policy.max_delay))
+}
+
+// Retry-After may either be a number of seconds or an HTTP date
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    value.parse::<u64>().map(Duration::from_secs).ok().or_else(|| {
+        DateTime::parse_from_rfc2822(value).ok().map(|date| {
+            let millis = date
+                .with_timezone(&Utc)
+                .signed_duration_since(Utc::now())
+                .num_milliseconds();
+
+            Duration::from_millis(cmp::max(millis, 0) as u64)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use config::RetryConfig;
+    use hooks::retry;
+
+    fn policy() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 5,
+            base_delay: 100,
+            max_delay: 1000,
+            jitter: false,
+            retry_statuses: vec![503],
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_max() {
+        let policy = policy();
+
+        assert_eq!(retry::delay(&policy, 1, None), Duration::from_millis(100));
+        assert_eq!(retry::delay(&policy, 2, None), Duration::from_millis(200));
+        assert_eq!(retry::delay(&policy, 3, None), Duration::from_millis(400));
+        assert_eq!(retry::delay(&policy, 5, None), Duration::from_millis(1000));
+        assert_eq!(retry::delay(&policy, 100, None), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jitter_stays_within_backoff() {
+        let mut policy = policy();
+        policy.jitter = true;
+
+        for _ in 0..50 {
+            let wait = retry::delay(&policy, 2, None);
+            assert!(wait >= Duration::from_millis(100) && wait <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn honours_longer_retry_after() {
+        let policy = policy();
+
+        assert_eq!(
+            retry::delay(&policy, 1, Some(Duration::from_millis(700))),
+            Duration::from_millis(700)
+        );
+        assert_eq!(
+            retry::delay(&policy, 3, Some(Duration::from_millis(10))),
+            Duration::from_millis(400)
+        );
+        assert_eq!(
+            retry::delay(&policy, 1, Some(Duration::from_secs(60))),
+            Duration::from_millis(1000)
+        );
+    }
+
+    #[test]
+    fn parses_retry_after_values() {
+        assert_eq!(retry::parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            retry::parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::from_millis(0))
+        );
+        assert_eq!(retry::parse_retry_after("soon"), None);
+    }
+}
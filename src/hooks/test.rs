@@ -0,0 +1,51 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use config::{Hook, HookConfig, HookKind};
+use hooks::Payload;
+
+// A show without a parent, which is what most hook tests deliver
+pub fn test_payload() -> Payload {
+    Payload::new(
+        json!({
+            "id": "test-child",
+            "type": "show",
+            "parent": null
+        }).as_object()
+            .unwrap()
+            .to_owned(),
+    )
+}
+
+// Registers hooks for shows, the type of the test payload
+pub fn show_hooks(hooks: Vec<Hook>) -> HookConfig {
+    let mut config = HookConfig::new();
+    config.insert("show".to_string(), hooks);
+    config
+}
+
+pub fn http_hook(url: &str) -> Hook {
+    Hook {
+        url: Some(url.to_string()),
+        ..Hook::default()
+    }
+}
+
+// An exec hook that runs a script with sh
+pub fn shell_hook(script: &str) -> Hook {
+    Hook {
+        kind: HookKind::Exec,
+        command: Some("sh".to_string()),
+        args: vec!["-c".to_string(), script.to_string()],
+        ..Hook::default()
+    }
+}
+
+// Creates an empty directory for a test to write its files to
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mm_api_notify_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,4 @@
+//! # mm_api_notify
+//!
+//! Helpers for services that receive notifications from mm_api_notify hooks
+pub mod signature;
@@ -9,10 +9,15 @@ extern crate chrono;
 extern crate clap;
 extern crate fern;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate log;
+extern crate mm_api_notify;
 extern crate mm_client;
 extern crate mongo_driver;
 extern crate rayon;
+extern crate rusqlite;
+#[macro_use]
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -24,27 +29,30 @@ mod config;
 mod error;
 mod hooks;
 mod objects;
+mod preflight;
 mod runtime;
 mod storage;
 mod types;
 
 use app_dirs::{get_app_dir, AppDataType, AppInfo};
 use chrono::offset::Utc;
-use chrono::{Duration, NaiveDateTime};
+use chrono::NaiveDateTime;
 use clap::{App, Arg};
 use serde_json::error::Result as JsonResult;
 use serde_json::Value as Json;
 
 use std::str::FromStr;
-use std::{thread, time};
+use std::{process, thread, time};
 
 use client::{APIClient, ClientResult, MMClient};
-use config::{parse_config, APIConfig, DBConfig};
+use config::{parse_config, validate_config, APIConfig, Config, DBConfig, DBEngine, HttpConfig,
+             ProxyConfig};
 use error::{IngestError, IngestResult};
-use hooks::Payload;
-use objects::{Collection, Importable, Object};
+use hooks::{DeliveryPool, EmitAction, HookClient, Outbox, Payload};
+use objects::{Collection, DeleteGuard, Importable, Object, Quarantine};
+use preflight::Preflight;
 use runtime::Runtime;
-use storage::{MongoStore, Storage};
+use storage::{Checkpoint, MongoStore, SqliteStore, Storage, StoreResult};
 use types::{RunResult, StorageEngine, ThreadedAPI};
 
 ///
@@ -116,6 +124,106 @@ fn main() {
                      runner will emit",
                 ),
         )
+        .arg(
+            Arg::with_name("hook")
+                .long("hook")
+                .takes_value(true)
+                .value_name("hook")
+                .requires("query")
+                .help(
+                    "Displays the body that the hook with this url or path will receive for the \
+                     query",
+                ),
+        )
+        .arg(
+            Arg::with_name("replay-dead-letters")
+                .long("replay-dead-letters")
+                .takes_value(true)
+                .min_values(0)
+                .value_name("hook")
+                .conflicts_with_all(&["build", "skip-update", "start-time", "query"])
+                .help(
+                    "Redelivers hook notifications from the dead letter queue in the order that \
+                     they were originally emitted. Optionally limited to a single hook url or path",
+                ),
+        )
+        .arg(
+            Arg::with_name("quarantine")
+                .long("quarantine")
+                .takes_value(false)
+                .conflicts_with_all(&["build", "skip-update", "start-time", "query"])
+                .help("Lists the deletes that the delete guard is holding in quarantine"),
+        )
+        .arg(
+            Arg::with_name("release-quarantine")
+                .long("release-quarantine")
+                .takes_value(true)
+                .min_values(0)
+                .value_name("id")
+                .conflicts_with_all(&["build", "skip-update", "start-time", "query", "quarantine"])
+                .help(
+                    "Performs the deletes held in quarantine and notifies hooks of them. \
+                     Optionally limited to the objects with a single id",
+                ),
+        )
+        .arg(
+            Arg::with_name("discard-quarantine")
+                .long("discard-quarantine")
+                .takes_value(true)
+                .min_values(0)
+                .value_name("id")
+                .conflicts_with_all(&[
+                    "build",
+                    "skip-update",
+                    "start-time",
+                    "query",
+                    "quarantine",
+                    "release-quarantine",
+                ])
+                .help(
+                    "Drops the deletes held in quarantine without performing them. Optionally \
+                     limited to the objects with a single id",
+                ),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "build",
+                    "skip-update",
+                    "start-time",
+                    "query",
+                    "replay-dead-letters",
+                    "quarantine",
+                    "release-quarantine",
+                    "discard-quarantine",
+                ])
+                .help(
+                    "Checks that the store, the Media Manager API, the hooks and the log location \
+                     can be reached and exits. The checks also run before every build or update",
+                ),
+        )
+        .arg(
+            Arg::with_name("validate-config")
+                .long("validate-config")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "build",
+                    "skip-update",
+                    "start-time",
+                    "query",
+                    "replay-dead-letters",
+                    "quarantine",
+                    "release-quarantine",
+                    "discard-quarantine",
+                    "check",
+                ])
+                .help(
+                    "Validates the configuration file, reporting errors with their line and \
+                     column and warning about unknown keys, and exits",
+                ),
+        )
         .get_matches();
 
     let config_path = if !matches.is_present("config") {
@@ -137,8 +245,29 @@ fn main() {
     }
     .expect("Failed to run. Unable to parse path to default config location.");
 
+    if matches.is_present("validate-config") {
+        let validation = validate_config(config_path.as_str());
+
+        for error in &validation.errors {
+            println!("error: {}", error);
+        }
+
+        for warning in &validation.warnings {
+            println!("warning: {}", warning);
+        }
+
+        if validation.errors.is_empty() {
+            println!("The config at {} is valid.", config_path);
+        }
+
+        process::exit(if validation.errors.is_empty() { 0 } else { 1 });
+    }
+
     let conf_res = parse_config(config_path.as_str())
-        .ok_or(IngestError::InvalidConfig)
+        .map_err(|err| {
+            eprintln!("{}", err);
+            IngestError::InvalidConfig
+        })
         .and_then(|config| {
             // Initialize logging
             if let &Some(ref log_location) = &config.log.location {
@@ -153,7 +282,7 @@ fn main() {
                     .or(config_log_level_filter)
                     .unwrap_or(log::LogLevelFilter::Warn);
 
-                fern::Dispatch::new()
+                let dispatch = fern::Dispatch::new()
                     .format(|out, message, record| {
                         out.finish(format_args!(
                             "[{}][{}] {}",
@@ -163,10 +292,26 @@ fn main() {
                         ))
                     })
                     .level(log_level)
-                    .chain(std::io::stdout())
-                    .chain(fern::log_file(log_location.as_str()).expect("Failed to open log file"))
-                    .apply()
-                    .expect("Failed to initialize logger");
+                    .chain(std::io::stdout());
+
+                // An unwritable log location is reported by the preflight checks
+                let dispatch = match fern::log_file(log_location.as_str()) {
+                    Ok(log_file) => dispatch.chain(log_file),
+                    Err(_) => dispatch,
+                };
+
+                dispatch.apply().expect("Failed to initialize logger");
+            }
+
+            let problems = config.validate();
+
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("Invalid config: {}", problem);
+                }
+
+                eprintln!("Run with --validate-config for details.");
+                return Err(IngestError::InvalidConfig);
             }
 
             // Initialize the thread pools
@@ -180,25 +325,139 @@ fn main() {
                 })
                 .and_then(|_| {
                     let store = get_store(&config.db);
-                    let api = get_api_client(&config.mm);
+                    let api = get_api_client(&config.mm, config.http.proxy.as_ref());
+                    let hook_client = get_hook_client(&config.http);
+
+                    // The checks run on their own with --check and before every build or update
+                    let maintenance = [
+                        "query",
+                        "replay-dead-letters",
+                        "quarantine",
+                        "release-quarantine",
+                        "discard-quarantine",
+                    ].iter()
+                        .any(|arg| matches.is_present(arg));
+
+                    if matches.is_present("check") || !maintenance {
+                        let preflight = Preflight::run(&config, store.as_ref(), &api, &hook_client);
+
+                        if matches.is_present("check") {
+                            println!("{}", preflight);
+                            process::exit(if preflight.passed() { 0 } else { 1 });
+                        }
+
+                        if !preflight.passed() {
+                            error!("Preflight checks\n{}", preflight);
+                            error!("Refusing to start as critical preflight checks failed");
+                            process::exit(1);
+                        }
+
+                        info!("Preflight checks\n{}", preflight);
+                    }
+
+                    let store = store.expect("Failed to connect to storage");
+                    let deliveries = get_delivery_pool(&config, &hook_client);
 
                     let runtime = Runtime {
                         api: api,
                         config: config,
                         store: store,
+                        hook_client,
+                        deliveries,
+                        guard: DeleteGuard::new(),
                     };
 
                     if let Some(query) = matches.values_of("query") {
                         let query_args = query.collect::<Vec<&str>>();
                         match runtime.store.get(query_args[1], query_args[0]) {
                             Some(Ok(obj)) => match Payload::from_object(&obj, &runtime.store) {
-                                Some(payload) => {
-                                    println!("{}", serde_json::to_string_pretty(&payload).unwrap())
-                                }
+                                Some(payload) => match matches.value_of("hook") {
+                                    Some(url) => print_hook_preview(
+                                        &runtime.config,
+                                        query_args[0],
+                                        url,
+                                        &payload,
+                                    ),
+                                    None => println!(
+                                        "{}",
+                                        serde_json::to_string_pretty(&payload).unwrap()
+                                    ),
+                                },
                                 None => error!("Failed to generate payload from object."),
                             },
-                            _ => println!("Could not find the requested object in the cache."),
+                            _ => match runtime.store.tombstone(query_args[1], query_args[0]) {
+                                Some(Ok(tombstone)) => {
+                                    println!(
+                                        "The requested object was deleted at {} because {}.",
+                                        NaiveDateTime::from_timestamp(tombstone.removed_at, 0),
+                                        tombstone.reason
+                                    );
+
+                                    if let Some(changelog) = tombstone.changelog {
+                                        println!(
+                                            "It was listed in the changelog as {} at {}.",
+                                            changelog.action, changelog.timestamp
+                                        );
+                                    }
+                                }
+                                _ => {
+                                    println!("Could not find the requested object in the cache.")
+                                }
+                            },
                         };
+                    } else if matches.is_present("replay-dead-letters") {
+                        match runtime.config.hooks {
+                            Some(ref hooks) => {
+                                let response = Outbox::new(&runtime.store)
+                                    .with_client(&runtime.hook_client)
+                                    .replay(hooks, matches.value_of("replay-dead-letters"));
+
+                                println!(
+                                    "Replayed {} dead letters with {} successes and {} failures.",
+                                    response.success.len() + response.failure.len(),
+                                    response.success.len(),
+                                    response.failure.len()
+                                );
+                            }
+                            None => println!("There are no hooks configured to replay to."),
+                        }
+                    } else if matches.is_present("quarantine") {
+                        let held = Quarantine::new(&runtime).held(None);
+
+                        for tombstone in &held {
+                            println!(
+                                "{} {} was held at {} because {}.",
+                                tombstone.object_type,
+                                tombstone.id,
+                                NaiveDateTime::from_timestamp(tombstone.removed_at, 0),
+                                tombstone.reason
+                            );
+                        }
+
+                        println!("There are {} deletes held in quarantine.", held.len());
+                    } else if matches.is_present("release-quarantine") {
+                        let quarantine = Quarantine::new(&runtime);
+                        let held = quarantine.held(matches.value_of("release-quarantine"));
+                        let count = held.len();
+
+                        let result = quarantine.release(held) + runtime.wait_for_deliveries();
+
+                        println!(
+                            "Released {} deletes from quarantine with {} successes and {} \
+                             failures.",
+                            count, result.success, result.failure
+                        );
+                    } else if matches.is_present("discard-quarantine") {
+                        let quarantine = Quarantine::new(&runtime);
+                        let held = quarantine.held(matches.value_of("discard-quarantine"));
+
+                        let result = quarantine.discard(&held);
+
+                        println!(
+                            "Discarded {} deletes from quarantine with {} failures.",
+                            held.len(),
+                            result.failure
+                        );
                     } else {
                         let time_arg = matches.value_of("start-time").map_or(0, |arg| {
                             arg.parse::<i64>().expect("Could not parse start time")
@@ -222,7 +481,7 @@ fn main() {
                             let update_start_time =
                                 if time_arg < (now - runtime.config.mm.changelog_max_timespan) {
                                     compute_update_start_time(
-                                        runtime.store.updated_at(),
+                                        last_checkpoint_time(&runtime.store),
                                         runtime.config.mm.changelog_max_timespan,
                                     )
                                 } else {
@@ -237,15 +496,72 @@ fn main() {
                 })
         });
 
-    conf_res.expect("Failed to parse config.")
+    match conf_res {
+        Ok(_) => (),
+        // The reason the config could not be used has already been printed
+        Err(IngestError::InvalidConfig) => process::exit(1),
+        Err(err) => {
+            eprintln!("Failed to start due to {}", err);
+            process::exit(1);
+        }
+    }
 }
 
-fn get_store(config: &DBConfig) -> MongoStore {
-    MongoStore::new(config).expect("Failed to connect to storage")
+fn print_hook_preview(config: &Config, object_type: &str, target: &str, payload: &Payload) {
+    let hook = config.hooks.as_ref().and_then(|hooks| {
+        hooks.get(object_type).and_then(|hooks| {
+            hooks
+                .iter()
+                .find(|hook| hook.target().map(|t| t.as_str()) == Some(target))
+        })
+    });
+
+    match hook {
+        Some(hook) => {
+            if !payload.matches(hook) {
+                println!("The hook's filter excludes this object. It would not be notified.");
+            }
+
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&payload.body(hook, &EmitAction::Update)).unwrap()
+            )
+        }
+        None => println!("There is no {} hook configured for {}.", object_type, target),
+    }
 }
 
-fn get_api_client(config: &APIConfig) -> MMClient {
-    MMClient::new(Some(config)).expect("Failed to initalize network client")
+fn get_store(config: &DBConfig) -> StoreResult<Box<dyn StorageEngine>> {
+    match config.engine {
+        DBEngine::Mongo => {
+            MongoStore::new(config).map(|store| Box::new(store) as Box<dyn StorageEngine>)
+        }
+        DBEngine::Sqlite => {
+            SqliteStore::new(config).map(|store| Box::new(store) as Box<dyn StorageEngine>)
+        }
+    }
+}
+
+fn get_api_client(config: &APIConfig, proxy: Option<&ProxyConfig>) -> MMClient {
+    MMClient::new(Some(config))
+        .and_then(|client| match proxy {
+            Some(proxy) => client.with_proxy(proxy),
+            None => Ok(client),
+        })
+        .expect("Failed to initalize network client")
+}
+
+fn get_hook_client(config: &HttpConfig) -> HookClient {
+    HookClient::new(config).expect("Failed to initialize hook client")
+}
+
+fn get_delivery_pool(config: &Config, client: &HookClient) -> Option<DeliveryPool> {
+    match config.hooks {
+        Some(ref hooks) if config.enable_hooks => {
+            Some(DeliveryPool::new(&config.delivery, hooks, client))
+        }
+        _ => None,
+    }
 }
 
 fn run_build<T: StorageEngine, S: ThreadedAPI>(
@@ -260,6 +576,7 @@ fn run_build<T: StorageEngine, S: ThreadedAPI>(
 
     let result = import_collection(runtime.api.all_shows(), runtime, run_start_time);
     print_runtime("Create", &result);
+    print_circuits(&runtime.hook_client);
 
     result
 }
@@ -277,6 +594,7 @@ fn run_show<T: StorageEngine, S: ThreadedAPI>(
 
     let result = import_object(runtime.api.show(id), runtime, run_start_time);
     print_runtime("Create", &result);
+    print_circuits(&runtime.hook_client);
 
     result
 }
@@ -294,10 +612,32 @@ fn run_franchise<T: StorageEngine, S: ThreadedAPI>(
 
     let result = import_object(runtime.api.franchise(id), runtime, run_start_time);
     print_runtime("Create", &result);
+    print_circuits(&runtime.hook_client);
 
     result
 }
 
+fn last_checkpoint_time<T: StorageEngine>(store: &T) -> Option<i64> {
+    match store.checkpoint() {
+        Some(Ok(checkpoint)) => {
+            info!(
+                "Resuming from update run {} that completed at {}",
+                checkpoint.run_id,
+                NaiveDateTime::from_timestamp(checkpoint.completed_at, 0)
+            );
+            Some(checkpoint.started_at)
+        }
+        Some(Err(err)) => {
+            error!("Failed to read the update checkpoint due to {}", err);
+            None
+        }
+        None => {
+            info!("No update checkpoint found. Resuming from the newest cached record.");
+            store.updated_at()
+        }
+    }
+}
+
 fn compute_update_start_time(last_updated_at: Option<i64>, max_timespan: i64) -> i64 {
     let newest_db_timestamp = last_updated_at.unwrap_or(0);
     let now = Utc::now().timestamp();
@@ -314,9 +654,27 @@ fn compute_update_start_time(last_updated_at: Option<i64>, max_timespan: i64) ->
     }
 }
 
+// Runs that keep failing retry from the same watermark, so the window they query would otherwise
+// keep growing past what the changelog allows
+fn limit_update_window(since: i64, now: i64, max_timespan: i64) -> i64 {
+    let earliest = now - max_timespan;
+
+    if since < earliest {
+        warn!(
+            "Update window from {} exceeds the maximum threshold for updates. Changes before {} \
+             that failed to import are no longer retried. Consider performing a build (-b) run \
+             to fully update the database.",
+            NaiveDateTime::from_timestamp(since, 0),
+            NaiveDateTime::from_timestamp(earliest, 0)
+        );
+        earliest
+    } else {
+        since
+    }
+}
+
 fn run_update_loop<T: StorageEngine, S: ThreadedAPI>(runtime: &Runtime<T, S>, run_start_time: i64) {
     let mut import_start_time = run_start_time;
-    let mut import_completion_time = Utc::now().timestamp();
     let mut next_run_time = run_start_time;
     let label = "Update";
 
@@ -328,24 +686,51 @@ fn run_update_loop<T: StorageEngine, S: ThreadedAPI>(runtime: &Runtime<T, S>, ru
 
     loop {
         if Utc::now().timestamp() > next_run_time {
-            next_run_time = Utc::now().timestamp() + runtime.config.min_runtime_delta;
+            // Deliveries that were interrupted by a previous shutdown or deferred by an open
+            // circuit are sent before any new ones
+            drain_outbox(runtime);
 
-            import_start_time = import_start_time - runtime.config.lookback_timeframe;
+            let run_started_at = Utc::now().timestamp();
+            next_run_time = run_started_at + runtime.config.min_runtime_delta;
+
+            let since = limit_update_window(
+                import_start_time - runtime.config.lookback_timeframe,
+                run_started_at,
+                runtime.config.mm.changelog_max_timespan,
+            );
 
             info!(
                 "Starting update run from {} : {}",
-                run_start_time,
-                NaiveDateTime::from_timestamp(import_start_time, 0)
+                since,
+                NaiveDateTime::from_timestamp(since, 0)
             );
 
-            let run_time = run_update(runtime, import_start_time);
+            let run_time = run_update(runtime, since);
 
             print_runtime(label, &run_time);
+            print_circuits(&runtime.hook_client);
 
-            import_start_time = import_completion_time;
-            import_completion_time = Utc::now().timestamp();
+            // Only a run that completed without failures advances the watermark. Otherwise the
+            // next run retries the same window, which is limited to the changelog timespan.
+            match run_time {
+                Ok((_, ref result)) if result.failure == 0 => {
+                    let checkpoint = Checkpoint::new(since, run_started_at);
+
+                    if let Err(err) = runtime.store.set_checkpoint(&checkpoint) {
+                        error!("Failed to persist the update checkpoint due to {}", err);
+                    }
+
+                    import_start_time = run_started_at;
+                }
+                _ => warn!(
+                    "{} run did not complete successfully. The next run will retry from {} : {}",
+                    label,
+                    import_start_time,
+                    NaiveDateTime::from_timestamp(import_start_time, 0)
+                ),
+            }
 
-            let diff = next_run_time - import_completion_time;
+            let diff = next_run_time - Utc::now().timestamp();
 
             if diff > 0 {
                 thread::sleep(time::Duration::from_secs(diff as u64));
@@ -354,6 +739,24 @@ fn run_update_loop<T: StorageEngine, S: ThreadedAPI>(runtime: &Runtime<T, S>, ru
     }
 }
 
+fn drain_outbox<T: StorageEngine, S: ThreadedAPI>(runtime: &Runtime<T, S>) {
+    if runtime.config.enable_hooks {
+        if let Some(ref hooks) = runtime.config.hooks {
+            let (success, failure) = Outbox::new(&runtime.store)
+                .with_client(&runtime.hook_client)
+                .drain(hooks)
+                .results();
+
+            if success + failure > 0 {
+                info!(
+                    "Drained the outbox with {} successes and {} failures.",
+                    success, failure
+                );
+            }
+        }
+    }
+}
+
 fn run_update<T: StorageEngine, S: ThreadedAPI>(
     runtime: &Runtime<T, S>,
     run_start_time: i64,
@@ -387,12 +790,14 @@ fn import_collection<T: StorageEngine, S: ThreadedAPI>(
         Err(err) => Err(IngestError::Client(err)),
     };
 
-    collection
-        .and_then(|coll| {
-            let res = coll.import(runtime, true, run_start_time);
-            Ok((Utc::now().signed_duration_since(start_time), res))
-        })
-        .or(Ok((Duration::seconds(0), (0, 1))))
+    collection.and_then(|coll| {
+        runtime.guard.begin();
+
+        let res = coll.import(runtime, true, run_start_time)
+            + runtime.guard.settle(runtime)
+            + runtime.wait_for_deliveries();
+        Ok((Utc::now().signed_duration_since(start_time), res))
+    })
 }
 
 fn import_object<T: StorageEngine, S: ThreadedAPI>(
@@ -413,12 +818,14 @@ fn import_object<T: StorageEngine, S: ThreadedAPI>(
         Err(err) => Err(IngestError::Client(err)),
     };
 
-    object
-        .and_then(|obj| {
-            let res = obj.import(runtime, true, run_start_time);
-            Ok((Utc::now().signed_duration_since(start_time), res))
-        })
-        .or(Ok((Duration::seconds(0), (0, 1))))
+    object.and_then(|obj| {
+        runtime.guard.begin();
+
+        let res = obj.import(runtime, true, run_start_time)
+            + runtime.guard.settle(runtime)
+            + runtime.wait_for_deliveries();
+        Ok((Utc::now().signed_duration_since(start_time), res))
+    })
 }
 
 fn print_runtime(label: &str, run_time: &IngestResult<RunResult>) {
@@ -428,16 +835,31 @@ fn print_runtime(label: &str, run_time: &IngestResult<RunResult>) {
     }
 }
 
-fn print_sucess(label: &str, &(dur, (pass, fail)): &RunResult) {
+fn print_sucess(label: &str, &(dur, ref result): &RunResult) {
     info!(
-        "{} run took {} seconds with {} successes and {} failures.",
+        "{} run took {} seconds with {} successes, {} unchanged, {} skipped and {} failures. \
+         {} notifications were delivered and {} failed.",
         label,
         dur.num_seconds(),
-        pass,
-        fail
+        result.success,
+        result.unchanged,
+        result.skipped,
+        result.failure,
+        result.delivered,
+        result.undelivered
     )
 }
 
 fn print_failure(label: &str, error: &IngestError) {
     error!("{} run failed to run to completion. {}", label, error)
 }
+
+fn print_circuits(client: &HookClient) {
+    for circuit in client.breakers().summary() {
+        warn!(
+            "Circuit for hook {} is {} after {} consecutive failures. {} deliveries were \
+             deferred.",
+            circuit.target, circuit.state, circuit.failures, circuit.deferred
+        )
+    }
+}
@@ -45,7 +45,7 @@ impl Collection {
         self.page
             .par_iter()
             .map(|item| item.import(runtime, follow_refs, since))
-            .reduce(|| (0, 0), |(p1, f1), (p2, f2)| (p1 + p2, f1 + f2))
+            .reduce(ImportResult::default, |a, b| a + b)
     }
 }
 
@@ -77,14 +77,14 @@ impl Importable for Collection {
                                     .and_then(|collection| {
                                         Ok(collection.import_page(runtime, follow_refs, since))
                                     })
-                                    .unwrap_or((0, 1))
+                                    .unwrap_or(ImportResult::new(0, 1))
                             })
-                            .reduce(|| (0, 0), |(p1, f1), (p2, f2)| (p1 + p2, f1 + f2)),
+                            .reduce(ImportResult::default, |a, b| a + b),
                     )
                 })
             })
             .or_else(|| Some(self.import_page(runtime, follow_refs, since)))
-            .unwrap_or((0, 1))
+            .unwrap_or(ImportResult::new(0, 1))
     }
 
     fn from_json(json: &Json) -> IngestResult<Collection> {
@@ -146,8 +146,9 @@ mod tests {
     use std::collections::HashSet;
     use std::iter::FromIterator;
 
-    use config::{APIConfig, Config, DBConfig, LogConfig};
-    use objects::{Collection, Importable, Ref};
+    use config::Config;
+    use hooks::HookClient;
+    use objects::{Collection, DeleteGuard, Importable, Ref};
     use runtime::Runtime;
     use storage::SinkStore;
     use client::{APIClient, TestClient};
@@ -206,33 +207,7 @@ mod tests {
 
         client.set_response("{}".to_string());
 
-        let empty = "".to_string();
-
-        let config = Config {
-            db: DBConfig {
-                host: empty.clone(),
-                port: 0,
-                name: empty.clone(),
-                username: empty.clone(),
-                password: empty.clone(),
-            },
-            mm: APIConfig {
-                key: empty.clone(),
-                secret: empty.clone(),
-                env: None,
-                changelog_max_timespan: 0,
-            },
-            thread_pool_size: 0,
-            min_runtime_delta: 0,
-            lookback_timeframe: 0,
-            ignore_skip: false,
-            log: LogConfig {
-                location: None,
-                level: None,
-            },
-            enable_hooks: false,
-            hooks: None,
-        };
+        let config = Config::empty();
 
         let reporter = client.clone();
 
@@ -240,6 +215,9 @@ mod tests {
             api: client,
             config: config,
             store: store,
+            hook_client: HookClient::shared().clone(),
+            deliveries: None,
+            guard: DeleteGuard::new(),
         };
 
         coll.import(&runtime, false, 0);
@@ -0,0 +1,327 @@
+extern crate serde_json;
+
+use self::serde_json::Value as Json;
+
+use std::mem;
+use std::sync::{Mutex, MutexGuard};
+
+use objects::reference::Ref;
+use runtime::Runtime;
+use storage::Tombstone;
+use types::{ImportResult, StorageEngine, ThreadedAPI};
+
+#[derive(Debug, Default)]
+struct GuardState {
+    refs: usize,
+    held: Vec<Tombstone>,
+}
+
+// Counts the references processed in a run and holds the deletes that the API caused, so that a
+// run which would delete suspiciously much can be stopped before any of it reaches the hooks
+#[derive(Debug, Default)]
+pub struct DeleteGuard {
+    state: Mutex<GuardState>,
+}
+
+impl DeleteGuard {
+    pub fn new() -> DeleteGuard {
+        DeleteGuard::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, GuardState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Starts counting a new run. Anything held by a run that was never settled is dropped, as the
+    // references that caused it are imported again when that run is retried
+    pub fn begin(&self) {
+        *self.state() = GuardState::default();
+    }
+
+    pub fn count_ref(&self) {
+        self.state().refs += 1;
+    }
+
+    pub fn hold(&self, tombstone: Tombstone) {
+        self.state().held.push(tombstone);
+    }
+
+    // Performs the deletes held during the run, unless there are more of them than the configured
+    // limits allow. Then they are moved to the quarantine to be reviewed instead.
+    pub fn settle<T: StorageEngine, S: ThreadedAPI>(
+        &self,
+        runtime: &Runtime<T, S>,
+    ) -> ImportResult {
+        let (refs, held) = {
+            let mut state = self.state();
+            let refs = mem::replace(&mut state.refs, 0);
+            (refs, mem::take(&mut state.held))
+        };
+
+        if !runtime.config.delete_guard.exceeded(held.len(), refs) {
+            return held.into_iter()
+                .fold(ImportResult::new(0, 0), |result, tombstone| {
+                    result + held_ref(&tombstone).delete(runtime, tombstone)
+                });
+        }
+
+        error!(
+            "Holding {} deletes caused by the {} references processed in this run in quarantine \
+             as they exceed the configured limits. Review them with --quarantine and release or \
+             discard them with --release-quarantine or --discard-quarantine.",
+            held.len(),
+            refs
+        );
+
+        held.iter()
+            .fold(ImportResult::new(0, 0), |result, tombstone| {
+                match runtime.store.quarantine(tombstone) {
+                    Ok(_) => result,
+                    Err(err) => {
+                        error!(
+                            "Failed to quarantine the delete of {} {} due to {}",
+                            tombstone.object_type, tombstone.id, err
+                        );
+                        result + ImportResult::new(0, 1)
+                    }
+                }
+            })
+    }
+}
+
+// Deletes that were held back by the guard, to be released or discarded after review
+pub struct Quarantine<'a, T: 'a + StorageEngine, S: 'a + ThreadedAPI> {
+    runtime: &'a Runtime<T, S>,
+}
+
+impl<'a, T: StorageEngine, S: ThreadedAPI> Quarantine<'a, T, S> {
+    pub fn new(runtime: &'a Runtime<T, S>) -> Quarantine<'a, T, S> {
+        Quarantine { runtime }
+    }
+
+    // The held deletes, optionally limited to the objects with a single id
+    pub fn held(&self, id: Option<&str>) -> Vec<Tombstone> {
+        self.runtime
+            .store
+            .quarantined()
+            .unwrap_or_else(|err| {
+                error!("Failed to read the quarantine due to {}", err);
+                vec![]
+            })
+            .into_iter()
+            .filter(|tombstone| id.map_or(true, |id| tombstone.id == id))
+            .collect()
+    }
+
+    // Performs held deletes and removes them from the quarantine
+    pub fn release(&self, held: Vec<Tombstone>) -> ImportResult {
+        held.into_iter()
+            .fold(ImportResult::new(0, 0), |result, tombstone| {
+                let refr = held_ref(&tombstone);
+
+                result + refr.delete(self.runtime, tombstone)
+                    + self.unquarantine(&refr.id, &refr.ref_type)
+            })
+    }
+
+    // Removes held deletes from the quarantine without performing them
+    pub fn discard(&self, held: &[Tombstone]) -> ImportResult {
+        held.iter()
+            .fold(ImportResult::new(0, 0), |result, tombstone| {
+                result + self.unquarantine(&tombstone.id, &tombstone.object_type)
+            })
+    }
+
+    fn unquarantine(&self, id: &str, object_type: &str) -> ImportResult {
+        match self.runtime.store.unquarantine(id, object_type) {
+            Ok(_) => ImportResult::new(0, 0),
+            Err(err) => {
+                error!(
+                    "Failed to remove {} {} from the quarantine due to {}",
+                    object_type, id, err
+                );
+                ImportResult::new(0, 1)
+            }
+        }
+    }
+}
+
+// Deletes only need the id and type of the reference, which is all that the tombstone records
+fn held_ref(tombstone: &Tombstone) -> Ref {
+    Ref::new(
+        tombstone.id.clone(),
+        Json::Object(Default::default()),
+        tombstone.object_type.clone(),
+        String::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::mock;
+
+    use std::collections::BTreeMap;
+
+    use config::{Config, DeleteGuardConfig, Hook};
+    use client::{APIClient, TestClient};
+    use hooks::HookClient;
+    use objects::{DeleteGuard, Importable, Quarantine, Ref};
+    use runtime::Runtime;
+    use storage::{RemovalReason, SinkStore, Storage, Tombstone};
+    use types::ImportResult;
+
+    // A runtime whose API key has lost access to every object
+    fn revoked_runtime(delete_guard: DeleteGuardConfig) -> Runtime<SinkStore, TestClient> {
+        let store = SinkStore::new(None).unwrap();
+        let mut client = TestClient::new(None).unwrap();
+        client.revoke_access();
+
+        let config = Config {
+            delete_guard,
+            ..Config::empty()
+        };
+
+        Runtime {
+            api: client,
+            config,
+            store,
+            hook_client: HookClient::shared().clone(),
+            deliveries: None,
+            guard: DeleteGuard::new(),
+        }
+    }
+
+    fn show_ref(id: &str) -> Ref {
+        Ref::from_json(&json!({
+            "id": id,
+            "type": "show",
+            "attributes": {},
+            "links": {
+                "self": format!("http://0.0.0.0/show/{}", id)
+            }
+        })).unwrap()
+    }
+
+    fn run(runtime: &Runtime<SinkStore, TestClient>, ids: &[&str]) -> ImportResult {
+        runtime.guard.begin();
+
+        ids.iter()
+            .map(|id| show_ref(id).import(runtime, false, 0))
+            .fold(ImportResult::new(0, 0), |a, b| a + b) + runtime.guard.settle(runtime)
+    }
+
+    fn held(id: &str) -> Tombstone {
+        Tombstone::new(
+            id.to_string(),
+            "show".to_string(),
+            RemovalReason::NotAuthorized,
+        )
+    }
+
+    #[test]
+    fn limits_deletes_by_count_and_fraction() {
+        let disabled = DeleteGuardConfig::default();
+        assert!(!disabled.enabled());
+        assert!(!disabled.exceeded(1000, 1000));
+
+        let by_count = DeleteGuardConfig {
+            max_deletes: Some(5),
+            ..DeleteGuardConfig::default()
+        };
+        assert!(!by_count.exceeded(5, 5));
+        assert!(by_count.exceeded(6, 100));
+
+        let by_fraction = DeleteGuardConfig {
+            max_fraction: Some(0.5),
+            min_deletes: 3,
+            ..DeleteGuardConfig::default()
+        };
+        assert!(!by_fraction.exceeded(2, 2));
+        assert!(!by_fraction.exceeded(3, 6));
+        assert!(by_fraction.exceeded(3, 5));
+    }
+
+    #[test]
+    fn quarantines_deletes_over_the_limit() {
+        let m = mock("DELETE", "/guard_quarantine/test-a/")
+            .with_status(200)
+            .expect(0)
+            .create();
+
+        let hook = Hook {
+            url: Some("http://127.0.0.1:1234/guard_quarantine/".to_string()),
+            ..Hook::default()
+        };
+
+        let mut hooks = BTreeMap::new();
+        hooks.insert("show".to_string(), vec![hook]);
+
+        let mut runtime = revoked_runtime(DeleteGuardConfig {
+            max_fraction: Some(0.5),
+            min_deletes: 1,
+            ..DeleteGuardConfig::default()
+        });
+        runtime.config.enable_hooks = true;
+        runtime.config.hooks = Some(hooks);
+
+        assert_eq!(run(&runtime, &["test-a", "test-b"]), ImportResult::new(0, 0));
+
+        m.assert();
+        assert!(runtime.store.get_removed().is_empty());
+
+        let quarantined = runtime.store.quarantined().unwrap();
+        let ids: Vec<&str> = quarantined.iter().map(|t| t.id.as_str()).collect();
+
+        assert_eq!(ids, vec!["test-a", "test-b"]);
+        assert_eq!(quarantined[0].reason, RemovalReason::NotAuthorized);
+    }
+
+    #[test]
+    fn performs_deletes_within_the_limit() {
+        let runtime = revoked_runtime(DeleteGuardConfig {
+            max_deletes: Some(5),
+            ..DeleteGuardConfig::default()
+        });
+
+        run(&runtime, &["test-a", "test-b"]);
+
+        assert_eq!(runtime.store.get_removed().len(), 2);
+        assert!(runtime.store.quarantined().unwrap().is_empty());
+    }
+
+    #[test]
+    fn deletes_immediately_without_limits() {
+        let runtime = revoked_runtime(DeleteGuardConfig::default());
+
+        show_ref("test-a").import(&runtime, false, 0);
+
+        assert_eq!(runtime.store.get_removed().len(), 1);
+    }
+
+    #[test]
+    fn releases_and_discards_quarantined_deletes() {
+        let runtime = revoked_runtime(DeleteGuardConfig::default());
+
+        runtime.store.quarantine(&held("test-a")).unwrap();
+        runtime.store.quarantine(&held("test-b")).unwrap();
+
+        let quarantine = Quarantine::new(&runtime);
+
+        let released = quarantine.held(Some("test-a"));
+        assert_eq!(quarantine.release(released), ImportResult::new(0, 0));
+
+        let removed = runtime.store.get_removed();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id, "test-a");
+        assert_eq!(removed[0].reason, RemovalReason::NotAuthorized);
+
+        let discarded = quarantine.held(None);
+        assert_eq!(discarded.len(), 1);
+        quarantine.discard(&discarded);
+
+        assert!(quarantine.held(None).is_empty());
+        assert_eq!(runtime.store.get_removed().len(), 1);
+    }
+}
@@ -1,10 +1,12 @@
 mod collection;
+mod guard;
 mod import;
 mod object;
 mod reference;
 pub mod utils;
 
 pub use self::collection::Collection;
+pub use self::guard::{DeleteGuard, Quarantine};
 pub use self::import::Importable;
-pub use self::object::Object;
+pub use self::object::{Object, OBJECT_TYPES};
 pub use self::reference::Ref;
@@ -9,9 +9,10 @@ use self::chrono::offset::Utc;
 use self::rayon::prelude::*;
 use self::serde_json::Value as Json;
 
+use std::collections::HashSet;
 use std::fmt;
 
-use hooks::{Emitter, HttpEmitter, Payload};
+use hooks::{Emitter, HookEmitter, Outbox, Payload};
 use error::IngestResult;
 use error::IngestError;
 use objects::Collection;
@@ -19,8 +20,11 @@ use objects::Importable;
 use objects::Ref;
 use objects::utils;
 use runtime::Runtime;
+use storage::StoreResult;
 use types::{ImportResult, StorageEngine, ThreadedAPI};
 
+pub const OBJECT_TYPES: [&str; 6] = ["asset", "episode", "franchise", "season", "show", "special"];
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Object {
     #[serde(rename = "_id")] pub id: String,
@@ -39,15 +43,30 @@ impl Object {
         }
     }
 
-    pub fn parent<T: StorageEngine>(&self, store: &T) -> Option<Object> {
-        match self.object_type.as_str() {
+    // The attribute that holds a reference to the parent of an object of the type
+    pub fn parent_key(object_type: &str) -> Option<&'static str> {
+        match object_type {
             "asset" => Some("parent_tree"),
             "episode" => Some("season"),
             "season" => Some("show"),
             "show" => Some("franchise"),
             "special" => Some("show"),
             _ => None,
-        }.and_then(|parent_key| {
+        }
+    }
+
+    // The types of object that reference a parent along with the attribute that references it
+    pub fn child_types() -> Vec<(&'static str, &'static str)> {
+        OBJECT_TYPES
+            .iter()
+            .filter_map(|object_type| {
+                Object::parent_key(object_type).map(|parent_key| (*object_type, parent_key))
+            })
+            .collect()
+    }
+
+    pub fn parent<T: StorageEngine>(&self, store: &T) -> Option<Object> {
+        Object::parent_key(self.object_type.as_str()).and_then(|parent_key| {
             self.attributes
                 .get(parent_key)
                 .and_then(|parent| Ref::from_json(parent).ok())
@@ -59,6 +78,52 @@ impl Object {
             })
     }
 
+    // Every cached object beneath the object with the id and type, ordered so that each object
+    // comes before its parent
+    pub fn descendants<T: StorageEngine>(
+        id: &str,
+        object_type: &str,
+        store: &T,
+    ) -> StoreResult<Vec<Object>> {
+        let mut descendants = vec![];
+        let mut seen = HashSet::new();
+
+        Object::collect_descendants(id, object_type, store, &mut seen, &mut descendants)?;
+
+        Ok(descendants)
+    }
+
+    fn collect_descendants<T: StorageEngine>(
+        id: &str,
+        object_type: &str,
+        store: &T,
+        seen: &mut HashSet<(String, String)>,
+        descendants: &mut Vec<Object>,
+    ) -> StoreResult<()> {
+        for child in store.children(id, object_type)? {
+            if seen.insert((child.id.clone(), child.object_type.clone())) {
+                Object::collect_descendants(
+                    child.id.as_str(),
+                    child.object_type.as_str(),
+                    store,
+                    seen,
+                    descendants,
+                )?;
+                descendants.push(child);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn updated_at(&self) -> Option<i64> {
+        self.attributes
+            .get("updated_at")
+            .and_then(|update_string| update_string.as_str())
+            .and_then(|updated_str| updated_str.parse::<DateTime<Utc>>().ok())
+            .map(|date| date.timestamp())
+    }
+
     pub fn from_bson(bson: Bson) -> IngestResult<Object> {
         bson::from_bson(utils::map_bson_dates_to_string(bson)).map_err(IngestError::Deserialize)
     }
@@ -84,6 +149,34 @@ impl Object {
         utils::map_string_to_bson_dates(self.as_json().into())
     }
 
+    fn has_changes_from(&self, cached: &Object, volatile_fields: &[String]) -> bool {
+        let normalize = |attributes: &Json| {
+            utils::normalize_json_dates(utils::remove_json_paths(
+                attributes.clone(),
+                volatile_fields,
+            ))
+        };
+
+        self.object_type != cached.object_type
+            || self.self_url != cached.self_url
+            || normalize(&self.attributes) != normalize(&cached.attributes)
+    }
+
+    fn import_related<T: StorageEngine, S: ThreadedAPI>(
+        &self,
+        runtime: &Runtime<T, S>,
+        follow_refs: bool,
+        since: i64,
+        result: ImportResult,
+    ) -> ImportResult {
+        if follow_refs {
+            result + self.import_children(runtime, follow_refs, since)
+                + self.import_parents(runtime, follow_refs, since)
+        } else {
+            result
+        }
+    }
+
     fn import_children<T: StorageEngine, S: ThreadedAPI>(
         &self,
         runtime: &Runtime<T, S>,
@@ -112,9 +205,9 @@ impl Object {
 
                         Some(child_collection.import(runtime, follow_refs, since))
                     })
-                    .unwrap_or((0, 1))
+                    .unwrap_or(ImportResult::new(0, 1))
             })
-            .reduce(|| (0, 0), |(p1, f1), (p2, f2)| (p1 + p2, f1 + f2))
+            .reduce(ImportResult::default, |a, b| a + b)
     }
 
     fn child_collection<T: ThreadedAPI>(&self, api: &T, child_type: &str) -> Option<Collection> {
@@ -148,10 +241,10 @@ impl Object {
             .map(|parent_type| match self.attributes.get(parent_type) {
                 Some(parent_obj) => Ref::from_json(parent_obj)
                     .and_then(|refr| Ok(refr.import(runtime, false, since)))
-                    .unwrap_or((0, 1)),
-                _ => (0, 0),
+                    .unwrap_or(ImportResult::new(0, 1)),
+                _ => ImportResult::default(),
             })
-            .reduce(|| (0, 0), |(p1, f1), (p2, f2)| (p1 + p2, f1 + f2))
+            .reduce(ImportResult::default, |a, b| a + b)
     }
 }
 
@@ -170,26 +263,78 @@ impl Importable for Object {
             self.attributes.get("title").unwrap().as_str().unwrap()
         );
 
-        let updated_at_time = self.attributes
-            .get("updated_at")
-            .and_then(|update_string| update_string.as_str())
-            .and_then(|updated_str| updated_str.parse::<DateTime<Utc>>().ok())
-            .and_then(|date| Some(date.timestamp()))
-            .unwrap_or(0);
+        let updated_at_time = self.updated_at().unwrap_or(0);
 
         // Check the updated_at date to determine if the db needs to
         // update this object
-        let mut update_result = if runtime.config.ignore_skip || updated_at_time >= since {
+        let update_result = if runtime.config.ignore_skip || updated_at_time >= since {
+            // An object that is cached but could not be read is not new to receivers, so it is
+            // emitted as an update without its changes
+            let (cached, is_cached) = match runtime
+                .store
+                .get(self.id.as_str(), self.object_type.as_str())
+            {
+                Some(Ok(cached_obj)) => (Some(cached_obj), true),
+                Some(Err(err)) => {
+                    error!("Failed to read cached {} due to {}", self, err);
+                    (None, true)
+                }
+                None => (None, false),
+            };
+
+            if let Some(ref cached_obj) = cached {
+                if !self.has_changes_from(cached_obj, &runtime.config.volatile_fields) {
+                    info!(
+                        "{:<10} {} {:<10} {}",
+                        "Unchanged",
+                        self.id,
+                        self.object_type,
+                        self.attributes
+                            .get("title")
+                            .and_then(|title| title.as_str())
+                            .unwrap_or("")
+                    );
+
+                    return self.import_related(
+                        runtime,
+                        follow_refs,
+                        since,
+                        ImportResult::unchanged(),
+                    );
+                }
+            }
+
             let res = runtime.store.put(self);
 
             if res.is_ok() && runtime.config.enable_hooks && runtime.config.hooks.is_some() {
                 Payload::from_object(self, &runtime.store)
+                    .map(|payload| match cached {
+                        Some(ref cached_obj) => payload.with_changes(cached_obj, self),
+                        None => payload,
+                    })
                     .and_then(|payload| {
                         runtime
                             .config
                             .hooks
                             .as_ref()
-                            .map(|hooks| payload.emitter(&hooks, HttpEmitter::new).update())
+                            .map(|hooks| {
+                                let emitter = payload
+                                    .emitter(&hooks, HookEmitter::new)
+                                    .with_outbox(Outbox::new(&runtime.store))
+                                    .with_client(&runtime.hook_client);
+
+                                let emitter = match runtime.deliveries {
+                                    Some(ref pool) => emitter.with_pool(pool),
+                                    None => emitter,
+                                };
+
+                                // Objects that were not cached before are new to receivers
+                                if is_cached {
+                                    emitter.update()
+                                } else {
+                                    emitter.create()
+                                }
+                            })
                     })
                     .or_else(|| {
                         error!("Failed to create payload from {}", self);
@@ -198,10 +343,10 @@ impl Importable for Object {
             };
 
             match res {
-                Ok(_) => (1, 0),
+                Ok(_) => ImportResult::new(1, 0),
                 Err(err) => {
                     error!("Failed to write {} to cache due to {}", self, err);
-                    (0, 1)
+                    ImportResult::new(0, 1)
                 }
             }
         } else {
@@ -214,24 +359,10 @@ impl Importable for Object {
                 updated_at_time,
                 since
             );
-            (0, 0)
+            ImportResult::skipped()
         };
 
-        if follow_refs {
-            let child_results = self.import_children(runtime, follow_refs, since);
-            update_result = (
-                update_result.0 + child_results.0,
-                update_result.1 + child_results.1,
-            );
-
-            let parent_results = self.import_parents(runtime, follow_refs, since);
-            update_result = (
-                update_result.0 + parent_results.0,
-                update_result.1 + parent_results.1,
-            );
-        };
-
-        update_result
+        self.import_related(runtime, follow_refs, since, update_result)
     }
 
     fn from_json(json: &Json) -> IngestResult<Object> {
@@ -288,57 +419,64 @@ mod tests {
 
     use bson::{Bson, Document};
     use chrono::{DateTime, Utc};
+    use mockito;
+    use mockito::{mock, Matcher};
     use serde_json;
     use serde_json::{Map, Value as Json};
 
     use std::collections::BTreeMap;
 
     use client::{APIClient, TestClient};
-    use config::{APIConfig, Config, DBConfig, LogConfig};
+    use config::{Config, DBConfig, DBEngine, Hook, HttpMethods};
     use error::IngestError;
-    use objects::{Importable, Object};
+    use hooks::HookClient;
+    use objects::{DeleteGuard, Importable, Object};
     use runtime::Runtime;
-    use storage::SinkStore;
+    use types::ImportResult;
+    use storage::{SinkStore, SqliteStore, Storage};
 
     fn void_runtime() -> Runtime<SinkStore, TestClient> {
         let store = SinkStore::new(None).unwrap();
         let client = TestClient::new(None).unwrap();
 
-        let empty = "".to_string();
-
-        let config = Config {
-            db: DBConfig {
-                host: empty.clone(),
-                port: 0,
-                name: empty.clone(),
-                username: empty.clone(),
-                password: empty.clone(),
-            },
-            mm: APIConfig {
-                key: empty.clone(),
-                secret: empty.clone(),
-                env: None,
-                changelog_max_timespan: 0,
-            },
-            thread_pool_size: 0,
-            min_runtime_delta: 0,
-            lookback_timeframe: 0,
-            ignore_skip: false,
-            log: LogConfig {
-                location: None,
-                level: None,
-            },
-            enable_hooks: false,
-            hooks: None,
-        };
+        let config = Config::empty();
 
         Runtime {
             api: client,
             config: config,
             store: store,
+            hook_client: HookClient::shared().clone(),
+            deliveries: None,
+            guard: DeleteGuard::new(),
         }
     }
 
+    fn child_object(id: &str, obj_type: &str, parent_id: &str, parent_type: &str) -> Object {
+        let mut attributes = Map::new();
+        attributes.insert(
+            Object::parent_key(obj_type).unwrap().to_string(),
+            json!({
+                "id": parent_id,
+                "type": parent_type,
+                "attributes": {},
+                "links": { "self": "http://0.0.0.0/parent" }
+            }),
+        );
+
+        Object::new(
+            id.to_string(),
+            Json::Object(attributes),
+            obj_type.to_string(),
+            "http://0.0.0.0/child".to_string(),
+        )
+    }
+
+    fn cached_version(obj_json: &Json) -> Object {
+        let mut cached = Object::from_json(obj_json).unwrap();
+        cached.attributes["updated_at"] = json!("2017-01-01T00:00:00Z");
+        cached
+    }
+
     #[test]
     fn translates_from_valid_json() {
         let test_obj = "{\"data\": {\"id\": \"test-id\", \"attributes\": {\"updated_at\": \
@@ -364,6 +502,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn orders_descendants_before_their_parents() {
+        let store = SqliteStore::new(&DBConfig {
+            engine: DBEngine::Sqlite,
+            host: "".to_string(),
+            port: 0,
+            name: "".to_string(),
+            username: "".to_string(),
+            password: "".to_string(),
+            path: Some(":memory:".to_string()),
+        }).unwrap();
+
+        let objects = vec![
+            child_object("test-season", "season", "test-show", "show"),
+            child_object("test-episode", "episode", "test-season", "season"),
+            child_object("test-asset", "asset", "test-episode", "episode"),
+            child_object("test-special", "special", "test-show", "show"),
+            child_object("other-episode", "episode", "other-season", "season"),
+        ];
+
+        for obj in &objects {
+            store.put(obj).unwrap();
+        }
+
+        let ids = Object::descendants("test-show", "show", &store)
+            .unwrap()
+            .into_iter()
+            .map(|obj| obj.id)
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            ids,
+            vec!["test-asset", "test-episode", "test-season", "test-special"]
+        );
+        assert!(
+            Object::descendants("test-asset", "asset", &store)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
     #[test]
     fn missing_required_fields_fail() {
         let missing_id = json!({
@@ -502,8 +681,10 @@ mod tests {
     fn emits_update_if_new() {
         let e = "http://0.0.0.0/".to_string();
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), e);
+        let hook = Hook {
+            url: Some(e),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert(
@@ -527,22 +708,123 @@ mod tests {
         let mut runtime = void_runtime();
         runtime.config.enable_hooks = true;
         runtime.config.hooks = Some(config);
-        runtime
-            .store
-            .set_response(Object::from_json(&obj_json).unwrap());
+        runtime.store.set_response(cached_version(&obj_json));
 
         let obj = Object::from_json(&obj_json).unwrap();
         let test_res = obj.import(&runtime, false, 0);
 
-        assert_eq!(test_res, (1, 0))
+        assert_eq!(test_res, ImportResult::new(1, 0))
+    }
+
+    fn changed_show(path: &str) -> (Json, Hook) {
+        let obj_json = json!({
+            "data": {
+                "id": "test-id",
+                "type": "show",
+                "attributes": {
+                    "title": "Test Show",
+                    "updated_at": "2017-02-21T20:42:27.010750Z"
+                }
+            },
+            "links": {
+                "self": ""
+            }
+        });
+
+        let hook = Hook {
+            url: Some(format!("{}{}", mockito::SERVER_URL, path)),
+            ..Hook::default()
+        };
+
+        (obj_json, hook)
+    }
+
+    #[test]
+    fn emits_create_if_not_cached() {
+        let m = mock("POST", "/object_emits_create/")
+            .with_status(200)
+            .match_body(Matcher::Regex("\"action\":\"created\"".to_string()))
+            .create();
+
+        let (obj_json, hook) = changed_show("/object_emits_create/");
+
+        let mut config = BTreeMap::new();
+        config.insert("show".to_string(), vec![hook]);
+
+        let mut runtime = void_runtime();
+        runtime.config.enable_hooks = true;
+        runtime.config.hooks = Some(config);
+        runtime.store.set_writable();
+
+        let obj = Object::from_json(&obj_json).unwrap();
+
+        assert_eq!(obj.import(&runtime, false, 0), ImportResult::new(1, 0));
+        m.assert();
+    }
+
+    #[test]
+    fn emits_update_if_cache_is_unreadable() {
+        let m = mock("POST", "/object_emits_unreadable/")
+            .with_status(200)
+            .match_body(Matcher::Regex("\"action\":\"updated\"".to_string()))
+            .create();
+
+        let (obj_json, hook) = changed_show("/object_emits_unreadable/");
+
+        let mut config = BTreeMap::new();
+        config.insert("show".to_string(), vec![hook]);
+
+        let mut runtime = void_runtime();
+        runtime.config.enable_hooks = true;
+        runtime.config.hooks = Some(config);
+        runtime.store.set_writable();
+        runtime.store.set_unreadable();
+
+        let obj = Object::from_json(&obj_json).unwrap();
+
+        assert_eq!(obj.import(&runtime, false, 0), ImportResult::new(1, 0));
+        m.assert();
+    }
+
+    #[test]
+    fn emits_update_with_hook_method_if_cached() {
+        let m = mock("PUT", "/object_emits_update/")
+            .with_status(200)
+            .match_body(Matcher::Regex("\"action\":\"updated\"".to_string()))
+            .create();
+
+        let (obj_json, hook) = changed_show("/object_emits_update/");
+
+        let hook = Hook {
+            methods: HttpMethods {
+                update: "put".to_string(),
+                ..HttpMethods::default()
+            },
+            ..hook
+        };
+
+        let mut config = BTreeMap::new();
+        config.insert("show".to_string(), vec![hook]);
+
+        let mut runtime = void_runtime();
+        runtime.config.enable_hooks = true;
+        runtime.config.hooks = Some(config);
+        runtime.store.set_response(cached_version(&obj_json));
+
+        let obj = Object::from_json(&obj_json).unwrap();
+
+        assert_eq!(obj.import(&runtime, false, 0), ImportResult::new(1, 0));
+        m.assert();
     }
 
     #[test]
     fn emits_old_update_if_ignoring_skips() {
         let e = "http://0.0.0.0/".to_string();
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), e);
+        let hook = Hook {
+            url: Some(e),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert(
@@ -567,22 +849,22 @@ mod tests {
         runtime.config.ignore_skip = true;
         runtime.config.enable_hooks = true;
         runtime.config.hooks = Some(config);
-        runtime
-            .store
-            .set_response(Object::from_json(&obj_json).unwrap());
+        runtime.store.set_response(cached_version(&obj_json));
 
         let obj = Object::from_json(&obj_json).unwrap();
         let test_res = obj.import(&runtime, false, Utc::now().timestamp());
 
-        assert_eq!(test_res, (1, 0))
+        assert_eq!(test_res, ImportResult::new(1, 0))
     }
 
     #[test]
     fn skips_emit_if_old() {
         let e = "http://0.0.0.0/".to_string();
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), e);
+        let hook = Hook {
+            url: Some(e),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert(
@@ -613,6 +895,64 @@ mod tests {
         let obj = Object::from_json(&obj_json).unwrap();
         let test_res = obj.import(&runtime, false, Utc::now().timestamp());
 
-        assert_eq!(test_res, (0, 0))
+        assert_eq!(test_res, ImportResult::skipped())
+    }
+
+    #[test]
+    fn skips_write_and_emit_if_unchanged() {
+        let obj_json = json!({
+            "data": {
+                "id": "test-id",
+                "type": "show",
+                "attributes": {
+                    "title": "Test Show",
+                    "updated_at": "2017-02-21T20:42:27.010750Z"
+                }
+            },
+            "links": {
+                "self": ""
+            }
+        });
+
+        let mut runtime = void_runtime();
+        runtime.config.ignore_skip = true;
+
+        // Cached dates only retain millisecond precision
+        let mut cached = Object::from_json(&obj_json).unwrap();
+        cached.attributes["updated_at"] = json!("2017-02-21T20:42:27.010Z");
+        runtime.store.set_response(cached);
+
+        let obj = Object::from_json(&obj_json).unwrap();
+        let test_res = obj.import(&runtime, false, 0);
+
+        assert_eq!(test_res, ImportResult::unchanged())
+    }
+
+    #[test]
+    fn ignores_volatile_fields_when_comparing() {
+        let obj_json = json!({
+            "data": {
+                "id": "test-id",
+                "type": "show",
+                "attributes": {
+                    "title": "Test Show",
+                    "updated_at": "2017-02-21T20:42:27.010750Z"
+                }
+            },
+            "links": {
+                "self": ""
+            }
+        });
+
+        let mut runtime = void_runtime();
+        runtime.store.set_response(cached_version(&obj_json));
+
+        let obj = Object::from_json(&obj_json).unwrap();
+
+        assert_eq!(obj.import(&runtime, false, 0), ImportResult::new(1, 0));
+
+        runtime.config.volatile_fields = vec!["updated_at".to_string()];
+
+        assert_eq!(obj.import(&runtime, false, 0), ImportResult::unchanged());
     }
 }
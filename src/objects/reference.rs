@@ -8,13 +8,14 @@ use self::serde_json::Value as Json;
 use std::fmt;
 
 use client::ClientError;
-use hooks::{Emitter, HttpEmitter, Payload};
+use hooks::{EmitAction, Emitter, HookEmitter, Outbox, Payload, Submission};
 use error::IngestResult;
 use error::IngestError;
 use objects::import::Importable;
 use objects::object::Object;
 use objects::utils;
 use runtime::Runtime;
+use storage::{ChangelogEntry, RemovalReason, StoreError, StoreResult, Tombstone};
 use types::{ImportResult, StorageEngine, ThreadedAPI};
 
 #[derive(Debug, PartialEq)]
@@ -49,6 +50,21 @@ impl Ref {
         }
     }
 
+    // The changelog entry that listed the reference, when it came from the changelog
+    fn changelog_entry(&self) -> Option<ChangelogEntry> {
+        let attribute = |key| {
+            self.attributes
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(|value| value.to_string())
+        };
+
+        match (attribute("action"), attribute("timestamp")) {
+            (Some(action), Some(timestamp)) => Some(ChangelogEntry { action, timestamp }),
+            _ => None,
+        }
+    }
+
     fn as_object(&self) -> IngestResult<Object> {
         if self.ref_type.as_str() == "asset" && self.attributes["parent_tree"] != Json::Null {
             Ok(Object::new(
@@ -62,21 +78,144 @@ impl Ref {
         }
     }
 
-    fn delete<T: StorageEngine, S: ThreadedAPI>(&self, runtime: &Runtime<T, S>) -> ImportResult {
-        if runtime.config.enable_hooks {
-            match runtime.config.hooks {
-                Some(ref hooks) => {
-                    info!("{:<10} {} {:<10}", "Deleting", self.id, self.ref_type);
+    fn tombstone(&self, reason: RemovalReason) -> Tombstone {
+        Tombstone::new(self.id.clone(), self.ref_type.clone(), reason)
+            .with_changelog(self.changelog_entry())
+    }
+
+    // Removes the object along with its cached descendants when deletes of its type cascade. The
+    // descendants are removed and their deletes delivered first so that receivers never hold a
+    // child whose parent is gone. The object is kept when any of its descendants could not be
+    // removed.
+    pub fn delete<T: StorageEngine, S: ThreadedAPI>(
+        &self,
+        runtime: &Runtime<T, S>,
+        tombstone: Tombstone,
+    ) -> ImportResult {
+        if !runtime.config.cascade_deletes.contains(&self.ref_type) {
+            return self.remove(runtime, tombstone, &mut vec![])
+                .unwrap_or_else(|err| self.removal_failed(&err));
+        }
+
+        let descendants = match Object::descendants(
+            self.id.as_str(),
+            self.ref_type.as_str(),
+            &runtime.store,
+        ) {
+            Ok(descendants) => descendants,
+            Err(err) => {
+                error!(
+                    "Failed to find cached descendants of {} {} due to {}",
+                    self.ref_type, self.id, err
+                );
+                return ImportResult::new(0, 1);
+            }
+        };
+
+        let mut cascaded = ImportResult::new(0, 0);
+        let mut submitted = vec![];
+
+        for obj in descendants {
+            let descendant = Ref::new(obj.id, obj.attributes, obj.object_type, obj.self_url);
+            let tombstone = Tombstone::new(
+                descendant.id.clone(),
+                descendant.ref_type.clone(),
+                RemovalReason::Parent,
+            );
+
+            match descendant.remove(runtime, tombstone, &mut submitted) {
+                Ok(result) => cascaded = cascaded + result,
+                Err(err) => {
+                    let result = descendant.removal_failed(&err);
+
+                    error!(
+                        "Keeping {} {} because its descendant {} {} could not be removed",
+                        self.ref_type, self.id, descendant.ref_type, descendant.id
+                    );
+                    return cascaded + result;
+                }
+            }
+        }
+
+        // Deletes handed off to the pool are only queued, so they have to be delivered before
+        // the delete of the object is. Their results are collected when the run waits on the pool.
+        if let Some(ref pool) = runtime.deliveries {
+            pool.wait_for(&submitted);
+        }
+
+        cascaded
+            + self.remove(runtime, tombstone, &mut vec![])
+                .unwrap_or_else(|err| self.removal_failed(&err))
+    }
+
+    // Removes the object from the store and emits its delete once it is gone. Deliveries handed off
+    // to the pool are added to the submitted ones. Nothing is emitted when the object could not be
+    // removed from the store.
+    fn remove<T: StorageEngine, S: ThreadedAPI>(
+        &self,
+        runtime: &Runtime<T, S>,
+        tombstone: Tombstone,
+        submitted: &mut Vec<Submission>,
+    ) -> StoreResult<ImportResult> {
+        info!(
+            "{:<10} {} {:<10} because {}",
+            "Removing", self.id, self.ref_type, tombstone.reason
+        );
+
+        // The last known state of the object has to be read before it is removed from the store
+        let payload = if runtime.config.enable_hooks && runtime.config.hooks.is_some() {
+            Some(Payload::from_deleted(self, &tombstone, &runtime.store))
+        } else {
+            None
+        };
+
+        runtime.store.remove(&tombstone)?;
+
+        let (pass, fail) = match (runtime.config.hooks.as_ref(), payload) {
+            (Some(hooks), Some(payload)) => {
+                info!("{:<10} {} {:<10}", "Deleting", self.id, self.ref_type);
+
+                let emitter = payload
+                    .emitter(hooks, HookEmitter::new)
+                    .with_outbox(Outbox::new(&runtime.store))
+                    .with_client(&runtime.hook_client);
 
-                    Payload::from_ref(self)
-                        .emitter(&hooks, HttpEmitter::new)
-                        .delete()
-                        .results()
+                // Deliveries handed off to the pool are counted when the run waits on it
+                match runtime.deliveries {
+                    Some(ref pool) => {
+                        submitted.extend(emitter.submit(pool, EmitAction::Delete));
+                        (0, 0)
+                    }
+                    None => emitter.delete().results(),
                 }
-                None => (0, 0),
             }
+            _ => (0, 0),
+        };
+
+        Ok(ImportResult::new(pass, fail))
+    }
+
+    fn removal_failed(&self, err: &StoreError) -> ImportResult {
+        error!(
+            "Failed to remove {} {} from cache due to {}",
+            self.ref_type, self.id, err
+        );
+
+        ImportResult::new(0, 1)
+    }
+
+    // An outage or a revoked API key looks the same as every object being deleted, so deletes
+    // the API causes are held until the end of the run when the guard is enabled
+    fn delete_guarded<T: StorageEngine, S: ThreadedAPI>(
+        &self,
+        runtime: &Runtime<T, S>,
+        reason: RemovalReason,
+    ) -> ImportResult {
+        if runtime.config.delete_guard.enabled() {
+            runtime.guard.hold(self.tombstone(reason));
+            ImportResult::new(0, 0)
         } else {
-            (0, 0)
+            self.delete(runtime, self.tombstone(reason))
         }
     }
 
@@ -94,10 +233,10 @@ impl Ref {
                     .and_then(|obj| Ok(obj.import(runtime, follow_refs, since)))
                     .or_else(|err| match err {
                         IngestError::Client(ClientError::API(MMCError::ResourceNotFound)) => {
-                            Ok(self.delete(runtime))
+                            Ok(self.delete_guarded(runtime, RemovalReason::NotFound))
                         }
                         IngestError::Client(ClientError::API(MMCError::NotAuthorized)) => {
-                            Ok(self.delete(runtime))
+                            Ok(self.delete_guarded(runtime, RemovalReason::NotAuthorized))
                         }
                         _ => {
                             warn!(
@@ -109,7 +248,7 @@ impl Ref {
                         }
                     })
             })
-            .unwrap_or((0, 1))
+            .unwrap_or(ImportResult::new(0, 1))
     }
 
     fn import_changelog<T: StorageEngine, S: ThreadedAPI>(
@@ -119,7 +258,9 @@ impl Ref {
         action: ImportAction,
     ) -> ImportResult {
         match action {
-            ImportAction::Delete => self.delete(runtime),
+            ImportAction::Delete => {
+                self.delete(runtime, self.tombstone(RemovalReason::Changelog))
+            }
             ImportAction::Update => self.import_general(runtime, false, since),
         }
     }
@@ -132,6 +273,8 @@ impl Importable for Ref {
         follow_refs: bool,
         since: i64,
     ) -> ImportResult {
+        runtime.guard.count_ref();
+
         // When importing a reference we branch based on an inspection of the attributes. If this
         // a changelog reference then we prefer to use a custom import.
         let action = self.attributes
@@ -192,56 +335,104 @@ impl fmt::Display for Ref {
 #[cfg(test)]
 mod tests {
 
-    use mockito::mock;
+    use mockito::{mock, Matcher};
     use serde_json::Map;
     use serde_json::Value as Json;
 
+    use rusqlite::Connection;
+
     use std::collections::BTreeMap;
+    use std::env;
+    use std::fs;
 
-    use config::{APIConfig, Config, DBConfig, LogConfig};
+    use config::{Config, DBConfig, DBEngine, DeliveryConfig, Hook};
     use client::{APIClient, TestClient};
     use error::IngestError;
-    use objects::{Importable, Ref};
-    use storage::SinkStore;
+    use hooks::test::{shell_hook, test_dir};
+    use hooks::{DeliveryPool, HookClient};
+    use objects::{DeleteGuard, Importable, Object, Ref};
+    use storage::{ChangelogEntry, RemovalReason, SinkStore, SqliteStore, Storage};
     use runtime::Runtime;
+    use types::ImportResult;
 
     fn void_runtime() -> Runtime<SinkStore, TestClient> {
         let store = SinkStore::new(None).unwrap();
         let client = TestClient::new(None).unwrap();
 
-        let empty = "".to_string();
-
-        let config = Config {
-            db: DBConfig {
-                host: empty.clone(),
-                port: 0,
-                name: empty.clone(),
-                username: empty.clone(),
-                password: empty.clone(),
-            },
-            mm: APIConfig {
-                key: empty.clone(),
-                secret: empty.clone(),
-                env: None,
-                changelog_max_timespan: 0,
-            },
-            thread_pool_size: 0,
-            min_runtime_delta: 0,
-            lookback_timeframe: 0,
-            ignore_skip: false,
-            log: LogConfig {
-                location: None,
-                level: None,
-            },
-            enable_hooks: false,
-            hooks: None,
-        };
+        let config = Config::empty();
 
         Runtime {
             api: client,
             config: config,
             store: store,
+            hook_client: HookClient::shared().clone(),
+            deliveries: None,
+            guard: DeleteGuard::new(),
+        }
+    }
+
+    fn cached_runtime() -> Runtime<SqliteStore, TestClient> {
+        cached_runtime_at(":memory:")
+    }
+
+    fn cached_runtime_at(path: &str) -> Runtime<SqliteStore, TestClient> {
+        let runtime = void_runtime();
+
+        let store = SqliteStore::new(&DBConfig {
+            engine: DBEngine::Sqlite,
+            path: Some(path.to_string()),
+            ..runtime.config.db.clone()
+        }).unwrap();
+
+        let child = |id: &str, obj_type: &str, key: &str, parent_id: &str, parent_type: &str| {
+            Object::new(
+                id.to_string(),
+                json!({
+                    key: {
+                        "id": parent_id,
+                        "type": parent_type,
+                        "attributes": {},
+                        "links": { "self": "http://0.0.0.0/parent" }
+                    }
+                }),
+                obj_type.to_string(),
+                "http://0.0.0.0/child".to_string(),
+            )
+        };
+
+        let objects = vec![
+            child("test-show", "show", "franchise", "test-franchise", "franchise"),
+            child("test-season", "season", "show", "test-show", "show"),
+            child("test-episode", "episode", "season", "test-season", "season"),
+            child("test-asset", "asset", "parent_tree", "test-episode", "episode"),
+        ];
+
+        for obj in &objects {
+            store.put(obj).unwrap();
         }
+
+        Runtime {
+            api: runtime.api,
+            config: runtime.config,
+            store,
+            hook_client: runtime.hook_client,
+            deliveries: None,
+            guard: DeleteGuard::new(),
+        }
+    }
+
+    fn show_delete() -> Ref {
+        Ref::from_json(&json!({
+            "id": "test-show",
+            "type": "show",
+            "attributes": {
+                "action": "delete",
+                "timestamp": "2017-02-21T20:42:27.010750Z"
+            },
+            "links": {
+                "self": ""
+            }
+        })).unwrap()
     }
 
     #[test]
@@ -361,8 +552,10 @@ mod tests {
 
         let e = "http://127.0.0.1:1234/reference_emit_delete/".to_string();
 
-        let mut hook = BTreeMap::new();
-        hook.insert("url".to_string(), e);
+        let hook = Hook {
+            url: Some(e),
+            ..Hook::default()
+        };
 
         let mut config = BTreeMap::new();
         config.insert(
@@ -389,6 +582,319 @@ mod tests {
         let refr = Ref::from_json(&ref_json).unwrap();
         let test_res = refr.import(&runtime, false, 0);
 
-        assert_eq!(test_res, (3, 0))
+        assert_eq!(test_res, ImportResult::new(3, 0))
+    }
+
+    #[test]
+    fn emits_delete_with_last_known_object() {
+        let _m = mock("DELETE", "/reference_emit_cached_delete/test-id/")
+            .with_status(200)
+            .match_body(
+                json!({
+                    "data": {
+                        "title": "Test Franchise",
+                        "id": "test-id",
+                        "type": "franchise",
+                        "parent": null,
+                        "deleted": true,
+                        "deletion": {
+                            "reason": "changelog",
+                            "changelog": {
+                                "action": "delete",
+                                "timestamp": "2017-02-21T20:42:27.010750Z"
+                            }
+                        }
+                    },
+                    "action": "deleted"
+                }).to_string()
+                    .as_str(),
+            )
+            .create();
+
+        let hook = Hook {
+            url: Some("http://127.0.0.1:1234/reference_emit_cached_delete/".to_string()),
+            ..Hook::default()
+        };
+
+        let mut config = BTreeMap::new();
+        config.insert("franchise".to_string(), vec![hook]);
+
+        let ref_json = json!({
+            "id": "test-id",
+            "type": "franchise",
+            "attributes": {
+                "action": "delete",
+                "timestamp": "2017-02-21T20:42:27.010750Z"
+            },
+            "links": {
+                "self": ""
+            }
+        });
+
+        let mut runtime = void_runtime();
+        runtime.config.enable_hooks = true;
+        runtime.config.hooks = Some(config);
+        runtime.store.set_response(Object::new(
+            "test-id".to_string(),
+            json!({ "title": "Test Franchise" }),
+            "franchise".to_string(),
+            "http://0.0.0.0/test".to_string(),
+        ));
+
+        let refr = Ref::from_json(&ref_json).unwrap();
+
+        assert_eq!(refr.import(&runtime, false, 0), ImportResult::new(1, 0));
+    }
+
+    #[test]
+    fn emits_reason_and_changelog_entry_with_delete() {
+        let m = mock("DELETE", "/reference_emit_reason/test-id/")
+            .with_status(200)
+            .match_body(Matcher::Regex(
+                r#""deletion":\{"reason":"not_found","changelog":\{"action":"update""#.to_string(),
+            ))
+            .create();
+
+        let hook = Hook {
+            url: Some("http://127.0.0.1:1234/reference_emit_reason/".to_string()),
+            ..Hook::default()
+        };
+
+        let mut config = BTreeMap::new();
+        config.insert("show".to_string(), vec![hook]);
+
+        // The object that the changelog lists as updated no longer exists upstream
+        let ref_json = json!({
+            "id": "test-id",
+            "type": "show",
+            "attributes": {
+                "action": "update",
+                "timestamp": "2017-02-21T20:42:27.010750Z"
+            },
+            "links": {
+                "self": "http://0.0.0.0/test"
+            }
+        });
+
+        let mut runtime = void_runtime();
+        runtime.config.enable_hooks = true;
+        runtime.config.hooks = Some(config);
+
+        let refr = Ref::from_json(&ref_json).unwrap();
+
+        assert_eq!(refr.import(&runtime, false, 0), ImportResult::new(1, 0));
+        m.assert();
+
+        let removed = runtime.store.get_removed();
+
+        assert_eq!(removed[0].reason, RemovalReason::NotFound);
+        assert_eq!(
+            removed[0].changelog,
+            Some(ChangelogEntry {
+                action: "update".to_string(),
+                timestamp: "2017-02-21T20:42:27.010750Z".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn removes_changelog_delete_from_store() {
+        let ref_json = json!({
+            "id": "test-id",
+            "type": "show",
+            "attributes": {
+                "action": "delete",
+                "timestamp": "2017-02-21T20:42:27.010750Z"
+            },
+            "links": {
+                "self": ""
+            }
+        });
+
+        let runtime = void_runtime();
+
+        let refr = Ref::from_json(&ref_json).unwrap();
+        let test_res = refr.import(&runtime, false, 0);
+
+        let removed = runtime.store.get_removed();
+
+        assert_eq!(test_res, ImportResult::new(0, 0));
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id, "test-id".to_string());
+        assert_eq!(removed[0].object_type, "show".to_string());
+        assert_eq!(removed[0].reason, RemovalReason::Changelog);
+    }
+
+    #[test]
+    fn cascades_deletes_to_cached_descendants() {
+        let mut runtime = cached_runtime();
+        runtime.config.cascade_deletes = vec!["show".to_string()];
+
+        assert_eq!(show_delete().import(&runtime, false, 0), ImportResult::new(0, 0));
+
+        for &(id, obj_type) in &[
+            ("test-show", "show"),
+            ("test-season", "season"),
+            ("test-episode", "episode"),
+            ("test-asset", "asset"),
+        ] {
+            assert!(runtime.store.get(id, obj_type).is_none());
+        }
+
+        assert_eq!(
+            runtime.store.tombstone("test-show", "show").unwrap().unwrap().reason,
+            RemovalReason::Changelog
+        );
+        assert_eq!(
+            runtime.store.tombstone("test-asset", "asset").unwrap().unwrap().reason,
+            RemovalReason::Parent
+        );
+    }
+
+    #[test]
+    fn emits_cascaded_deletes() {
+        // The mock server closes connections after responding, so they must not be reused
+        let m = mock("DELETE", Matcher::Regex("^/reference_emit_cascade/".to_string()))
+            .with_status(200)
+            .with_header("connection", "close")
+            .expect(4)
+            .create();
+
+        let hook = Hook {
+            url: Some("http://127.0.0.1:1234/reference_emit_cascade/".to_string()),
+            ..Hook::default()
+        };
+
+        let mut config = BTreeMap::new();
+        for obj_type in &["show", "season", "episode", "asset"] {
+            config.insert(obj_type.to_string(), vec![hook.clone()]);
+        }
+
+        let mut runtime = cached_runtime();
+        runtime.config.cascade_deletes = vec!["show".to_string()];
+        runtime.config.enable_hooks = true;
+        runtime.config.hooks = Some(config);
+
+        assert_eq!(show_delete().import(&runtime, false, 0), ImportResult::new(4, 0));
+        m.assert();
+    }
+
+    #[test]
+    fn delivers_cascaded_deletes_before_the_parent() {
+        let output = env::temp_dir().join("mm_api_notify_reference_cascade_order_test");
+        let _ = fs::remove_file(&output);
+
+        let mut hook = shell_hook("echo \"$MM_NOTIFY_TYPE\" >> \"$0\"");
+        hook.args.push(output.to_string_lossy().into_owned());
+
+        let mut config = BTreeMap::new();
+        for obj_type in &["show", "season", "episode", "asset"] {
+            config.insert(obj_type.to_string(), vec![hook.clone()]);
+        }
+
+        let mut runtime = cached_runtime();
+        runtime.config.cascade_deletes = vec!["show".to_string()];
+        runtime.config.enable_hooks = true;
+        runtime.deliveries = Some(DeliveryPool::new(
+            &DeliveryConfig::default(),
+            &config,
+            HookClient::shared(),
+        ));
+        runtime.config.hooks = Some(config);
+
+        show_delete().import(&runtime, false, 0);
+        runtime.wait_for_deliveries();
+
+        let delivered = fs::read_to_string(&output).unwrap();
+        let types: Vec<&str> = delivered.lines().collect();
+
+        assert_eq!(types.len(), 4);
+        assert_eq!(types.last(), Some(&"show"));
+    }
+
+    #[test]
+    fn keeps_objects_whose_descendants_could_not_be_removed() {
+        let path = env::temp_dir().join("mm_api_notify_reference_cascade_failure_test.db");
+        let _ = fs::remove_file(&path);
+
+        let mut runtime = cached_runtime_at(path.to_str().unwrap());
+        runtime.config.cascade_deletes = vec!["show".to_string()];
+
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER keep BEFORE DELETE ON objects WHEN old.id = 'test-season' \
+                 BEGIN SELECT RAISE(ABORT, 'kept'); END;",
+            )
+            .unwrap();
+
+        assert_eq!(show_delete().import(&runtime, false, 0), ImportResult::new(0, 1));
+
+        assert!(runtime.store.get("test-asset", "asset").is_none());
+        assert!(runtime.store.get("test-season", "season").is_some());
+        assert!(runtime.store.get("test-show", "show").is_some());
+        assert!(runtime.store.tombstone("test-show", "show").is_none());
+    }
+
+    #[test]
+    fn emits_nothing_for_objects_that_could_not_be_removed() {
+        let path = env::temp_dir().join("mm_api_notify_reference_remove_failure_test.db");
+        let _ = fs::remove_file(&path);
+
+        let dir = test_dir("reference_remove_failure_test");
+        let emitted = dir.join("emitted");
+
+        let mut hooks = BTreeMap::new();
+        hooks.insert(
+            "show".to_string(),
+            vec![shell_hook(&format!("touch {}", emitted.display()))],
+        );
+
+        let mut runtime = cached_runtime_at(path.to_str().unwrap());
+        runtime.config.enable_hooks = true;
+        runtime.config.hooks = Some(hooks);
+
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER keep BEFORE DELETE ON objects WHEN old.id = 'test-show' \
+                 BEGIN SELECT RAISE(ABORT, 'kept'); END;",
+            )
+            .unwrap();
+
+        assert_eq!(show_delete().import(&runtime, false, 0), ImportResult::new(0, 1));
+
+        assert!(runtime.store.get("test-show", "show").is_some());
+        assert!(!emitted.exists());
+    }
+
+    #[test]
+    fn leaves_descendants_of_types_that_do_not_cascade() {
+        let runtime = cached_runtime();
+
+        assert_eq!(show_delete().import(&runtime, false, 0), ImportResult::new(0, 0));
+
+        assert!(runtime.store.get("test-show", "show").is_none());
+        assert!(runtime.store.get("test-season", "season").is_some());
+        assert!(runtime.store.get("test-asset", "asset").is_some());
+    }
+
+    #[test]
+    fn removes_missing_object_from_store() {
+        let runtime = void_runtime();
+
+        let test_ref = Ref::new(
+            "test-id".to_string(),
+            Json::Object(Map::new()),
+            "show".to_string(),
+            "http://0.0.0.0/test".to_string(),
+        );
+
+        test_ref.import(&runtime, false, 0);
+
+        let removed = runtime.store.get_removed();
+
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].reason, RemovalReason::NotFound);
     }
 }
@@ -6,6 +6,7 @@ extern crate serde_json;
 use self::bson::Bson;
 use self::chrono::DateTime;
 use self::chrono::offset::Utc;
+use self::serde_json::Map;
 use self::serde_json::Value as Json;
 
 use client::ClientResult;
@@ -60,6 +61,85 @@ pub fn map_bson_dates_to_string(bson: Bson) -> Bson {
     }
 }
 
+pub fn remove_json_paths(json: Json, paths: &[String]) -> Json {
+    paths.iter().fold(json, |mut json, path| {
+        remove_json_path(&mut json, path.split('.').collect::<Vec<&str>>().as_slice());
+        json
+    })
+}
+
+fn remove_json_path(json: &mut Json, path: &[&str]) {
+    match path.split_first() {
+        Some((key, [])) => {
+            json.as_object_mut().map(|map| map.remove(*key));
+        }
+        Some((key, rest)) => {
+            if let Some(child) = json.get_mut(*key) {
+                remove_json_path(child, rest);
+            }
+        }
+        None => (),
+    }
+}
+
+pub fn take_json_path(json: &mut Json, path: &str) -> Option<Json> {
+    let keys = path.split('.').collect::<Vec<&str>>();
+
+    keys.split_last().and_then(|(last, parents)| {
+        parents
+            .iter()
+            .try_fold(json, |json, key| json.get_mut(*key))
+            .and_then(|parent| parent.as_object_mut())
+            .and_then(|map| map.remove(*last))
+    })
+}
+
+// Inserts a value at a dotted path, creating any missing objects along the way
+pub fn insert_json_path(json: &mut Json, path: &str, value: Json) {
+    let keys = path.split('.').collect::<Vec<&str>>();
+
+    if let Some((last, parents)) = keys.split_last() {
+        let parent = parents.iter().try_fold(json, |json, key| {
+            json.as_object_mut().map(|map| {
+                let child = map.entry(key.to_string())
+                    .or_insert_with(|| Json::Object(Map::new()));
+
+                if !child.is_object() {
+                    *child = Json::Object(Map::new());
+                }
+
+                child
+            })
+        });
+
+        if let Some(map) = parent.and_then(|json| json.as_object_mut()) {
+            map.insert(last.to_string(), value);
+        }
+    }
+}
+
+pub fn normalize_json_dates(json: Json) -> Json {
+    // Dates are compared at millisecond precision as that is all that some storage engines retain
+    match json {
+        Json::String(string) => match string.parse::<DateTime<Utc>>() {
+            Ok(datetime) => Json::String(datetime.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()),
+            _ => Json::String(string),
+        },
+        Json::Object(map) => Json::Object(
+            map.into_iter()
+                .map(|(key, json_val)| (key, normalize_json_dates(json_val)))
+                .collect(),
+        ),
+        Json::Array(elements) => Json::Array(
+            elements
+                .into_iter()
+                .map(normalize_json_dates)
+                .collect::<Vec<Json>>(),
+        ),
+        x => x,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use bson::Bson;
@@ -136,4 +216,86 @@ mod tests {
             _ => panic!("Failed to properly handle API error"),
         }
     }
+
+    #[test]
+    fn removes_nested_json_paths() {
+        let json = json!({
+            "title": "Test",
+            "updated_at": "2017-01-01T00:00:00Z",
+            "images": [],
+            "parent_tree": {
+                "id": "test-parent",
+                "attributes": {
+                    "updated_at": "2017-01-01T00:00:00Z"
+                }
+            }
+        });
+
+        let paths = vec![
+            "updated_at".to_string(),
+            "parent_tree.attributes.updated_at".to_string(),
+            "missing.path".to_string(),
+        ];
+
+        let expected = json!({
+            "title": "Test",
+            "images": [],
+            "parent_tree": {
+                "id": "test-parent",
+                "attributes": {}
+            }
+        });
+
+        assert_eq!(utils::remove_json_paths(json, &paths), expected);
+    }
+
+    #[test]
+    fn normalizes_json_dates_to_milliseconds() {
+        let precise = json!({
+            "updated_at": "2017-02-21T20:42:27.010750Z",
+            "title": "Test"
+        });
+
+        let truncated = json!({
+            "updated_at": "2017-02-21T20:42:27.010Z",
+            "title": "Test"
+        });
+
+        assert_eq!(
+            utils::normalize_json_dates(precise),
+            utils::normalize_json_dates(truncated)
+        );
+    }
+
+    #[test]
+    fn moves_values_between_json_paths() {
+        let mut json = json!({
+            "title": "Test",
+            "parent_tree": {
+                "id": "test-parent"
+            }
+        });
+
+        let id = utils::take_json_path(&mut json, "parent_tree.id");
+        assert_eq!(id, Some(json!("test-parent")));
+        assert_eq!(utils::take_json_path(&mut json, "parent_tree.missing"), None);
+
+        utils::insert_json_path(&mut json, "parent.ids.first", id.unwrap());
+        utils::insert_json_path(&mut json, "title.text", json!("Test"));
+
+        assert_eq!(
+            json,
+            json!({
+                "title": {
+                    "text": "Test"
+                },
+                "parent_tree": {},
+                "parent": {
+                    "ids": {
+                        "first": "test-parent"
+                    }
+                }
+            })
+        );
+    }
 }
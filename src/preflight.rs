@@ -0,0 +1,326 @@
+extern crate chrono;
+extern crate reqwest;
+
+use self::chrono::offset::Utc;
+use self::reqwest::{Method, StatusCode};
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::iter;
+
+use config::{Config, Hook, HookKind};
+use hooks::HookClient;
+use storage::StoreError;
+use types::{StorageEngine, ThreadedAPI};
+
+// The outcome of a single preflight check. The service does not start when a critical one fails.
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub critical: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: String, critical: bool, result: Result<String, String>) -> Check {
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+
+        Check {
+            name,
+            passed,
+            critical,
+            detail,
+        }
+    }
+
+    fn result(&self) -> &'static str {
+        match (self.passed, self.critical) {
+            (true, _) => "pass",
+            (false, true) => "FAIL",
+            (false, false) => "warn",
+        }
+    }
+}
+
+// Verifies that the configured services can be reached before any objects are imported, so that
+// misconfigurations show up once instead of as a failure for every object
+#[derive(Debug, Default)]
+pub struct Preflight {
+    pub checks: Vec<Check>,
+}
+
+impl Preflight {
+    pub fn run<T: StorageEngine, S: ThreadedAPI>(
+        config: &Config,
+        store: Result<&T, &StoreError>,
+        api: &S,
+        hook_client: &HookClient,
+    ) -> Preflight {
+        let mut checks = vec![check_store(store), check_api(api)];
+
+        if let Some(ref location) = config.log.location {
+            checks.push(check_log(location));
+        }
+
+        if config.enable_hooks {
+            if let Some(ref hooks) = config.hooks {
+                let mut probed = BTreeSet::new();
+
+                for hook in hooks.values().flat_map(|hooks| hooks.iter()) {
+                    if let (HookKind::Http, Some(url)) = (&hook.kind, hook.url.as_ref()) {
+                        if probed.insert(url.clone()) {
+                            checks.push(check_hook(hook, url, hook_client));
+                        }
+                    }
+                }
+            }
+        }
+
+        Preflight { checks }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.passed || !check.critical)
+    }
+}
+
+impl fmt::Display for Preflight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.checks
+            .iter()
+            .map(|check| check.name.len())
+            .fold("Check".len(), usize::max);
+
+        let header = ("Check", "Result", "Detail");
+        let rows = self.checks
+            .iter()
+            .map(|check| (check.name.as_str(), check.result(), check.detail.as_str()));
+
+        for (name, result, detail) in iter::once(header).chain(rows) {
+            writeln!(f, "{:<width$}  {:<6}  {}", name, result, detail, width = width)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn check_store<T: StorageEngine>(store: Result<&T, &StoreError>) -> Check {
+    let result = store
+        .map_err(|err| format!("could not be opened: {}", err))
+        .and_then(|store| {
+            store
+                .check()
+                .map(|_| "reachable and writable".to_string())
+                .map_err(|err| format!("could not be written to: {}", err))
+        });
+
+    Check::new("store".to_string(), true, result)
+}
+
+// The changelog is the lightest endpoint that requires a valid key and secret
+fn check_api<S: ThreadedAPI>(api: &S) -> Check {
+    let now = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+
+    let result = api.changes(now.as_str())
+        .map(|_| "fetched the changelog".to_string())
+        .map_err(|err| format!("failed to fetch the changelog: {}", err));
+
+    Check::new("mm credentials".to_string(), true, result)
+}
+
+fn check_log(location: &str) -> Check {
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(location)
+        .map(|_| format!("{} is writable", location))
+        .map_err(|err| format!("{} is not writable: {}", location, err));
+
+    Check::new("log location".to_string(), true, result)
+}
+
+// Any answer short of a server error shows that the hook resolves and is listening. Hooks that
+// only accept notifications may reject HEAD requests, so those are retried with OPTIONS.
+// Undeliverable notifications are kept in the outbox, so an unreachable hook is not critical.
+fn check_hook(hook: &Hook, url: &str, hook_client: &HookClient) -> Check {
+    let probe = |method: Method| {
+        hook_client
+            .client(&hook.tls)
+            .map_err(|err| err.to_string())
+            .and_then(|client| {
+                client
+                    .request(method.clone(), url)
+                    .timeout(hook_client.timeout(hook))
+                    .send()
+                    .map(|response| (method, response.status()))
+                    .map_err(|err| err.to_string())
+            })
+    };
+
+    let result = probe(Method::HEAD)
+        .and_then(|(method, status)| match status {
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => {
+                probe(Method::OPTIONS)
+            }
+            _ => Ok((method, status)),
+        })
+        .and_then(|(method, status)| {
+            let detail = format!("{} answered {}", method, status);
+
+            if status.is_server_error() {
+                Err(detail)
+            } else {
+                Ok(detail)
+            }
+        });
+
+    Check::new(format!("hook {}", url), false, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::mock;
+
+    use std::collections::BTreeMap;
+
+    use config::{Config, Hook};
+    use client::{APIClient, TestClient};
+    use hooks::HookClient;
+    use preflight::Preflight;
+    use storage::{SinkStore, StoreError};
+
+    fn api() -> TestClient {
+        let mut client = TestClient::new(None).unwrap();
+        client.set_response("{}".to_string());
+        client
+    }
+
+    fn results(preflight: &Preflight) -> Vec<(&str, &str)> {
+        preflight
+            .checks
+            .iter()
+            .map(|check| (check.name.as_str(), check.result()))
+            .collect()
+    }
+
+    #[test]
+    fn passes_when_everything_is_reachable() {
+        let store = SinkStore::new(None).unwrap();
+
+        let preflight = Preflight::run(&Config::empty(), Ok(&store), &api(), HookClient::shared());
+
+        assert_eq!(
+            results(&preflight),
+            vec![("store", "pass"), ("mm credentials", "pass")]
+        );
+        assert!(preflight.passed());
+    }
+
+    #[test]
+    fn fails_when_critical_checks_fail() {
+        let mut config = Config::empty();
+        config.log.location = Some("/nonexistent/mm_api_notify.log".to_string());
+
+        let mut api = api();
+        api.revoke_access();
+
+        let err = StoreError::ConnectionError("mongodb://0.0.0.0:0".to_string());
+
+        let preflight = Preflight::run::<SinkStore, TestClient>(
+            &config,
+            Err(&err),
+            &api,
+            HookClient::shared(),
+        );
+
+        assert_eq!(
+            results(&preflight),
+            vec![
+                ("store", "FAIL"),
+                ("mm credentials", "FAIL"),
+                ("log location", "FAIL"),
+            ]
+        );
+        assert!(!preflight.passed());
+
+        let table = preflight.to_string();
+        assert!(table.starts_with("Check           Result  Detail\n"));
+        assert!(table.contains("\nstore           FAIL    could not be opened: "));
+    }
+
+    #[test]
+    fn probes_each_hook_once() {
+        // The mock server closes connections after responding, so they must not be reused
+        let _head = mock("HEAD", "/preflight_head/")
+            .with_status(200)
+            .with_header("connection", "close")
+            .create();
+        let _rejected = mock("HEAD", "/preflight_options/")
+            .with_status(405)
+            .with_header("connection", "close")
+            .create();
+        let _options = mock("OPTIONS", "/preflight_options/")
+            .with_status(204)
+            .with_header("connection", "close")
+            .create();
+        let _down = mock("HEAD", "/preflight_down/").with_status(503).create();
+
+        let hook = |path: &str| Hook {
+            url: Some(format!("http://127.0.0.1:1234/{}/", path)),
+            ..Hook::default()
+        };
+
+        let mut hooks = BTreeMap::new();
+        hooks.insert(
+            "show".to_string(),
+            vec![
+                hook("preflight_head"),
+                hook("preflight_options"),
+                hook("preflight_down"),
+            ],
+        );
+        hooks.insert("asset".to_string(), vec![hook("preflight_head")]);
+
+        let mut config = Config::empty();
+        config.enable_hooks = true;
+        config.hooks = Some(hooks);
+
+        let store = SinkStore::new(None).unwrap();
+
+        let preflight = Preflight::run(&config, Ok(&store), &api(), HookClient::shared());
+
+        let hooks: Vec<(&str, &str, &str)> = preflight.checks[2..]
+            .iter()
+            .map(|check| (check.name.as_str(), check.result(), check.detail.as_str()))
+            .collect();
+
+        assert_eq!(
+            hooks,
+            vec![
+                (
+                    "hook http://127.0.0.1:1234/preflight_head/",
+                    "pass",
+                    "HEAD answered 200 OK",
+                ),
+                (
+                    "hook http://127.0.0.1:1234/preflight_options/",
+                    "pass",
+                    "OPTIONS answered 204 No Content",
+                ),
+                (
+                    "hook http://127.0.0.1:1234/preflight_down/",
+                    "warn",
+                    "HEAD answered 503 Service Unavailable",
+                ),
+            ]
+        );
+        assert!(preflight.passed());
+    }
+}
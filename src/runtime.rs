@@ -1,5 +1,7 @@
 use config::Config;
-use types::{StorageEngine, ThreadedAPI};
+use hooks::{DeliveryPool, HookClient, Outbox};
+use objects::DeleteGuard;
+use types::{ImportResult, StorageEngine, ThreadedAPI};
 
 pub struct Runtime<T, S>
 where
@@ -9,4 +11,25 @@ where
     pub api: S,
     pub config: Config,
     pub store: T,
+    pub hook_client: HookClient,
+    pub deliveries: Option<DeliveryPool>,
+    pub guard: DeleteGuard,
+}
+
+impl<T, S> Runtime<T, S>
+where
+    T: StorageEngine,
+    S: ThreadedAPI,
+{
+    // Waits for the deliveries that were handed off during a run so that they are part of its
+    // results
+    pub fn wait_for_deliveries(&self) -> ImportResult {
+        match self.deliveries {
+            Some(ref pool) => {
+                let (delivered, undelivered) = pool.wait(Some(Outbox::new(&self.store)));
+                ImportResult::deliveries(delivered, undelivered)
+            }
+            None => ImportResult::default(),
+        }
+    }
 }
@@ -0,0 +1,217 @@
+extern crate chrono;
+extern crate hmac;
+extern crate sha2;
+
+use self::chrono::offset::Utc;
+use self::hmac::{Hmac, Mac, NewMac};
+use self::sha2::Sha256;
+
+use std::fmt;
+
+pub const SIGNATURE_HEADER: &str = "X-MM-Notify-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-MM-Notify-Timestamp";
+
+const SIGNATURE_PREFIX: &str = "sha256=";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, PartialEq)]
+pub enum SignatureError {
+    InvalidTimestamp,
+    Expired,
+    InvalidSignature,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignatureError::InvalidTimestamp => write!(f, "The signature timestamp is invalid"),
+            SignatureError::Expired => {
+                write!(f, "The signature timestamp is outside of the allowed window")
+            }
+            SignatureError::InvalidSignature => write!(f, "The signature does not match the body"),
+        }
+    }
+}
+
+fn mac(secret: &str, timestamp: &str, body: &[u8]) -> HmacSha256 {
+    // HMAC accepts keys of any length so creating the mac can not fail
+    let mut mac = HmacSha256::new_varkey(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+// Signs the timestamp and the exact request body, returning the value of the signature header
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let digest = mac(secret, timestamp.to_string().as_str(), body)
+        .finalize()
+        .into_bytes();
+
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!("{}{}", SIGNATURE_PREFIX, hex)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect()
+}
+
+// Verifies the signature and timestamp headers of a received notification. Signatures whose
+// timestamp is further than `tolerance` seconds from `now` are rejected so that a captured request
+// can not be replayed later.
+pub fn verify_at(
+    secret: &str,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+    tolerance: i64,
+    now: i64,
+) -> Result<(), SignatureError> {
+    let sent_at = timestamp
+        .parse::<i64>()
+        .map_err(|_| SignatureError::InvalidTimestamp)?;
+
+    // The timestamp is sent by the client, so it can be far enough from now to overflow
+    let expired = now.checked_sub(sent_at)
+        .and_then(i64::checked_abs)
+        .map_or(true, |age| age > tolerance);
+
+    if expired {
+        return Err(SignatureError::Expired);
+    }
+
+    let expected = signature
+        .strip_prefix(SIGNATURE_PREFIX)
+        .and_then(decode_hex)
+        .ok_or(SignatureError::InvalidSignature)?;
+
+    mac(secret, timestamp, body)
+        .verify(&expected)
+        .map_err(|_| SignatureError::InvalidSignature)
+}
+
+// Verification helper for receivers of notifications, checked against the current time
+pub fn verify(
+    secret: &str,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+    tolerance: i64,
+) -> Result<(), SignatureError> {
+    verify_at(
+        secret,
+        timestamp,
+        body,
+        signature,
+        tolerance,
+        Utc::now().timestamp(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify, verify_at, SignatureError};
+
+    const SECRET: &str = "test-secret";
+    const BODY: &[u8] = b"{\"data\":{\"id\":\"test-id\"}}";
+
+    #[test]
+    fn signs_body_with_timestamp() {
+        let signature = sign(SECRET, 1485907200, BODY);
+
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), 71);
+        assert_eq!(signature, sign(SECRET, 1485907200, BODY));
+        assert!(signature != sign(SECRET, 1485907201, BODY));
+        assert!(signature != sign("other-secret", 1485907200, BODY));
+    }
+
+    #[test]
+    fn verifies_valid_signatures() {
+        let signature = sign(SECRET, 1485907200, BODY);
+
+        assert_eq!(
+            verify_at(SECRET, "1485907200", BODY, signature.as_str(), 300, 1485907260),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_bodies_and_signatures() {
+        let signature = sign(SECRET, 1485907200, BODY);
+
+        assert_eq!(
+            verify_at(SECRET, "1485907200", b"{}", signature.as_str(), 300, 1485907200),
+            Err(SignatureError::InvalidSignature)
+        );
+        assert_eq!(
+            verify_at("other-secret", "1485907200", BODY, signature.as_str(), 300, 1485907200),
+            Err(SignatureError::InvalidSignature)
+        );
+        assert_eq!(
+            verify_at(SECRET, "1485907200", BODY, "sha256=zz", 300, 1485907200),
+            Err(SignatureError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_signatures_outside_replay_window() {
+        let signature = sign(SECRET, 1485907200, BODY);
+
+        assert_eq!(
+            verify_at(SECRET, "1485907200", BODY, signature.as_str(), 300, 1485907501),
+            Err(SignatureError::Expired)
+        );
+        assert_eq!(
+            verify_at(SECRET, "1485907200", BODY, signature.as_str(), 300, 1485906899),
+            Err(SignatureError::Expired)
+        );
+        assert_eq!(
+            verify(SECRET, "1485907200", BODY, signature.as_str(), 300),
+            Err(SignatureError::Expired)
+        );
+    }
+
+    #[test]
+    fn rejects_timestamps_that_overflow() {
+        let signature = sign(SECRET, 1485907200, BODY);
+        let min = i64::MIN.to_string();
+        let max = i64::MAX.to_string();
+
+        assert_eq!(
+            verify_at(SECRET, min.as_str(), BODY, signature.as_str(), 300, 1485907200),
+            Err(SignatureError::Expired)
+        );
+        assert_eq!(
+            verify_at(SECRET, max.as_str(), BODY, signature.as_str(), 300, -1),
+            Err(SignatureError::Expired)
+        );
+    }
+
+    #[test]
+    fn rejects_resigned_timestamps() {
+        // Moving the timestamp forward without the secret invalidates the signature
+        let signature = sign(SECRET, 1485907200, BODY);
+
+        assert_eq!(
+            verify_at(SECRET, "1485907500", BODY, signature.as_str(), 300, 1485907500),
+            Err(SignatureError::InvalidSignature)
+        );
+        assert_eq!(
+            verify_at(SECRET, "yesterday", BODY, signature.as_str(), 300, 1485907200),
+            Err(SignatureError::InvalidTimestamp)
+        );
+    }
+}
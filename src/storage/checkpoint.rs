@@ -0,0 +1,26 @@
+extern crate chrono;
+
+use self::chrono::offset::Utc;
+
+use std::process;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub run_id: String,
+    pub since: i64,
+    pub started_at: i64,
+    pub completed_at: i64,
+}
+
+impl Checkpoint {
+    pub fn new(since: i64, started_at: i64) -> Checkpoint {
+        let now = Utc::now();
+
+        Checkpoint {
+            run_id: format!("{:x}-{}", now.timestamp_nanos(), process::id()),
+            since,
+            started_at,
+            completed_at: now.timestamp(),
+        }
+    }
+}
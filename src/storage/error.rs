@@ -8,6 +8,7 @@ pub type StoreResult<T> = Result<T, StoreError>;
 #[derive(Debug)]
 pub enum StoreError {
     UriParseError(String),
+    ConnectionError(String),
     InvalidItemError(IngestError),
     StorageFindError,
     StorageWriteError,
@@ -19,6 +20,9 @@ impl fmt::Display for StoreError {
             StoreError::UriParseError(ref conn) => {
                 write!(f, "Failed to parse connection string {:?}", conn)
             }
+            StoreError::ConnectionError(ref conn) => {
+                write!(f, "Failed to open storage at {:?}", conn)
+            }
             StoreError::InvalidItemError(ref err) => err.fmt(f),
             StoreError::StorageFindError => write!(f, "Failed to return a document"),
             StoreError::StorageWriteError => write!(f, "Failed to write to storage"),
@@ -1,10 +1,19 @@
+mod checkpoint;
 mod error;
 mod mongo;
+mod queue;
 #[cfg(test)]
 mod sink;
+mod sqlite;
 mod storage;
+mod tombstone;
 
+pub use self::checkpoint::Checkpoint;
+pub use self::error::{StoreError, StoreResult};
 pub use self::mongo::MongoStore;
+pub use self::queue::Queue;
 #[cfg(test)]
 pub use self::sink::SinkStore;
+pub use self::sqlite::SqliteStore;
 pub use self::storage::Storage;
+pub use self::tombstone::{ChangelogEntry, RemovalReason, Tombstone};
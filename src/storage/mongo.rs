@@ -1,16 +1,30 @@
 extern crate bson;
+extern crate chrono;
 extern crate mongo_driver;
 
 use bson::Bson;
+use chrono::offset::Utc;
 use mongo_driver::CommandAndFindOptions;
 use mongo_driver::client::{ClientPool, Uri};
-use mongo_driver::collection::UpdateOptions;
-use mongo_driver::flags::UpdateFlag;
+use mongo_driver::collection::{FindAndModifyOperation, FindAndModifyOptions, RemoveOptions,
+                               UpdateOptions};
+use mongo_driver::flags::{RemoveFlag, UpdateFlag};
 
 use config::DBConfig;
+use error::IngestError;
+use hooks::OutboxEntry;
 use objects::{utils, Object};
+use storage::checkpoint::Checkpoint;
 use storage::error::{StoreError, StoreResult};
+use storage::queue::Queue;
 use storage::storage::{Storage, StorageStatus};
+use storage::tombstone::Tombstone;
+
+const TOMBSTONE_COLLECTION: &str = "tombstone";
+const CHECKPOINT_COLLECTION: &str = "checkpoint";
+const QUARANTINE_COLLECTION: &str = "quarantine";
+const PREFLIGHT_COLLECTION: &str = "preflight";
+const UPDATE_CHECKPOINT_ID: &str = "update";
 
 pub struct MongoStore {
     config: DBConfig,
@@ -78,17 +92,144 @@ impl Storage<Object> for MongoStore {
                     "_id" => id
                 };
 
+                // Only the id of the replaced document is needed to tell whether it existed
+                let mut opts = FindAndModifyOptions::default();
+                opts.fields = Some(doc! { "_id" => 1 });
+
+                coll.find_and_modify(&filter, FindAndModifyOperation::Upsert(&doc), Some(&opts))
+                    .map(|reply| {
+                        reply
+                            .get_document("lastErrorObject")
+                            .and_then(|status| status.get_bool("updatedExisting"))
+                            .unwrap_or(false)
+                    })
+                    .map_err(|_| StoreError::StorageWriteError)
+            })
+            .and_then(|existed| {
+                // Cached objects are never tombstoned, so only an object that is inserted can
+                // have been removed before. It has been restored upstream, so any tombstone for
+                // it is no longer valid.
+                if existed {
+                    return Ok(StorageStatus::Available);
+                }
+
+                let client = self.pool.pop();
+                let tombstones =
+                    client.get_collection(self.config.name.as_str(), TOMBSTONE_COLLECTION);
+
+                let filter = doc! {
+                    "_id" => item.id.as_str(),
+                    "type" => item.object_type.as_str()
+                };
+
+                tombstones
+                    .remove(&filter, None)
+                    .map(|_| StorageStatus::Available)
+                    .map_err(|_| StoreError::StorageWriteError)
+            })
+    }
+
+    fn remove(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus> {
+        bson::to_bson(tombstone)
+            .map_err(|err| StoreError::InvalidItemError(IngestError::Serialize(err)))
+            .and_then(|tombstone_bson| match tombstone_bson {
+                Bson::Document(doc) => Ok(doc),
+                _ => Err(StoreError::InvalidItemError(
+                    IngestError::InvalidDocumentDataError,
+                )),
+            })
+            .and_then(|doc| {
+                let client = self.pool.pop();
+                let tombstones =
+                    client.get_collection(self.config.name.as_str(), TOMBSTONE_COLLECTION);
+
+                let filter = doc! {
+                    "_id" => tombstone.id.as_str()
+                };
+
                 let mut opts = UpdateOptions::default();
                 opts.update_flags.add(UpdateFlag::Upsert);
 
-                coll.update(&filter, &doc, Some(&opts))
-                    .map(|_| StorageStatus::Available)
-                    .or_else(|_| Err(StoreError::StorageWriteError))
+                tombstones
+                    .update(&filter, &doc, Some(&opts))
+                    .map_err(|_| StoreError::StorageWriteError)
+                    .and_then(|_| {
+                        let coll = client.get_collection(
+                            self.config.name.as_str(),
+                            tombstone.object_type.as_str(),
+                        );
+
+                        let mut opts = RemoveOptions::default();
+                        opts.remove_flags.add(RemoveFlag::SingleRemove);
+
+                        coll.remove(&filter, Some(&opts))
+                            .map(|_| StorageStatus::Removed)
+                            .map_err(|_| StoreError::StorageWriteError)
+                    })
             })
     }
 
+    fn tombstone(&self, id: &str, obj_type: &str) -> Option<StoreResult<Tombstone>> {
+        let query = doc!{
+            "_id" => id,
+            "type" => obj_type
+        };
+
+        let client = self.pool.pop();
+        let coll = client.get_collection(self.config.name.as_str(), TOMBSTONE_COLLECTION);
+
+        let res = coll.find(&query, None).ok().and_then(|mut cursor| {
+            cursor.next().map(|res| {
+                res.map_err(|err| {
+                    error!(
+                        "Failed to get tombstone for {} from the Mongo store due to {}",
+                        id, err
+                    );
+                    StoreError::StorageFindError
+                }).and_then(|doc| {
+                    bson::from_bson(Bson::Document(doc))
+                        .map_err(|err| StoreError::InvalidItemError(IngestError::Deserialize(err)))
+                })
+            })
+        });
+
+        res
+    }
+
+    fn children(&self, id: &str, obj_type: &str) -> StoreResult<Vec<Object>> {
+        let client = self.pool.pop();
+        let mut children = vec![];
+
+        for (child_type, parent_key) in Object::child_types() {
+            let coll = client.get_collection(self.config.name.as_str(), child_type);
+
+            let query = doc! {
+                format!("attributes.{}.id", parent_key) => id,
+                format!("attributes.{}.type", parent_key) => obj_type
+            };
+
+            let cursor = coll.find(&query, None).map_err(|err| {
+                error!(
+                    "Failed to find children of {} in the Mongo store due to {}",
+                    id, err
+                );
+                StoreError::StorageFindError
+            })?;
+
+            for res in cursor {
+                let doc = res.map_err(|_| StoreError::StorageFindError)?;
+
+                children.push(Object::from_bson(utils::map_bson_dates_to_string(
+                    Bson::Document(doc),
+                )).map_err(StoreError::InvalidItemError)?);
+            }
+        }
+
+        Ok(children)
+    }
+
     fn updated_at(&self) -> Option<i64> {
-        let collections = vec!["asset", "episode", "season", "show", "special"];
+        let collections = ["asset", "episode", "franchise", "season", "show", "special"];
         let mut opts = CommandAndFindOptions::default();
         opts.limit = 1;
         let client = self.pool.pop();
@@ -130,4 +271,232 @@ impl Storage<Object> for MongoStore {
                 None => Some(cur),
             })
     }
+
+    fn checkpoint(&self) -> Option<StoreResult<Checkpoint>> {
+        let query = doc!{
+            "_id" => UPDATE_CHECKPOINT_ID
+        };
+
+        let client = self.pool.pop();
+        let coll = client.get_collection(self.config.name.as_str(), CHECKPOINT_COLLECTION);
+
+        let res = coll.find(&query, None).ok().and_then(|mut cursor| {
+            cursor.next().map(|res| {
+                res.map_err(|err| {
+                    error!("Failed to get checkpoint from the Mongo store due to {}", err);
+                    StoreError::StorageFindError
+                }).and_then(|doc| {
+                    bson::from_bson(Bson::Document(doc))
+                        .map_err(|err| StoreError::InvalidItemError(IngestError::Deserialize(err)))
+                })
+            })
+        });
+
+        res
+    }
+
+    fn set_checkpoint(&self, checkpoint: &Checkpoint) -> StoreResult<StorageStatus> {
+        bson::to_bson(checkpoint)
+            .map_err(|err| StoreError::InvalidItemError(IngestError::Serialize(err)))
+            .and_then(|checkpoint_bson| match checkpoint_bson {
+                Bson::Document(doc) => Ok(doc),
+                _ => Err(StoreError::InvalidItemError(
+                    IngestError::InvalidDocumentDataError,
+                )),
+            })
+            .and_then(|doc| {
+                let client = self.pool.pop();
+                let coll = client.get_collection(self.config.name.as_str(), CHECKPOINT_COLLECTION);
+
+                let filter = doc! {
+                    "_id" => UPDATE_CHECKPOINT_ID
+                };
+
+                let mut opts = UpdateOptions::default();
+                opts.update_flags.add(UpdateFlag::Upsert);
+
+                coll.update(&filter, &doc, Some(&opts))
+                    .map(|_| StorageStatus::Available)
+                    .map_err(|_| StoreError::StorageWriteError)
+            })
+    }
+
+    fn enqueue(&self, queue: Queue, entry: &OutboxEntry) -> StoreResult<StorageStatus> {
+        bson::to_bson(entry)
+            .map_err(|err| StoreError::InvalidItemError(IngestError::Serialize(err)))
+            .and_then(|entry_bson| match entry_bson {
+                Bson::Document(doc) => Ok(doc),
+                _ => Err(StoreError::InvalidItemError(
+                    IngestError::InvalidDocumentDataError,
+                )),
+            })
+            .and_then(|doc| {
+                let client = self.pool.pop();
+                let coll = client.get_collection(self.config.name.as_str(), queue.name());
+
+                let filter = doc! {
+                    "_id" => entry.id.as_str()
+                };
+
+                let mut opts = UpdateOptions::default();
+                opts.update_flags.add(UpdateFlag::Upsert);
+
+                coll.update(&filter, &doc, Some(&opts))
+                    .map(|_| StorageStatus::Available)
+                    .map_err(|_| StoreError::StorageWriteError)
+            })
+    }
+
+    fn dequeue(&self, queue: Queue, id: &str) -> StoreResult<StorageStatus> {
+        let filter = doc! {
+            "_id" => id
+        };
+
+        let client = self.pool.pop();
+        let coll = client.get_collection(self.config.name.as_str(), queue.name());
+
+        let mut opts = RemoveOptions::default();
+        opts.remove_flags.add(RemoveFlag::SingleRemove);
+
+        coll.remove(&filter, Some(&opts))
+            .map(|_| StorageStatus::Removed)
+            .map_err(|_| StoreError::StorageWriteError)
+    }
+
+    fn queued(&self, queue: Queue) -> StoreResult<Vec<OutboxEntry>> {
+        let query = doc! {
+            "$query" => {},
+            "$orderby" => {
+                "_id" => 1
+            }
+        };
+
+        let client = self.pool.pop();
+        let coll = client.get_collection(self.config.name.as_str(), queue.name());
+
+        coll.find(&query, None)
+            .map_err(|err| {
+                error!(
+                    "Failed to read the {} queue from the Mongo store due to {}",
+                    queue.name(),
+                    err
+                );
+                StoreError::StorageFindError
+            })
+            .and_then(|cursor| {
+                cursor
+                    .map(|res| {
+                        res.map_err(|_| StoreError::StorageFindError)
+                            .and_then(|doc| {
+                                bson::from_bson(Bson::Document(doc)).map_err(|err| {
+                                    StoreError::InvalidItemError(IngestError::Deserialize(err))
+                                })
+                            })
+                    })
+                    .collect()
+            })
+    }
+
+    fn quarantine(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus> {
+        bson::to_bson(tombstone)
+            .map_err(|err| StoreError::InvalidItemError(IngestError::Serialize(err)))
+            .and_then(|tombstone_bson| match tombstone_bson {
+                Bson::Document(doc) => Ok(doc),
+                _ => Err(StoreError::InvalidItemError(
+                    IngestError::InvalidDocumentDataError,
+                )),
+            })
+            .and_then(|doc| {
+                let client = self.pool.pop();
+                let coll = client.get_collection(self.config.name.as_str(), QUARANTINE_COLLECTION);
+
+                let filter = doc! {
+                    "_id" => tombstone.id.as_str()
+                };
+
+                let mut opts = UpdateOptions::default();
+                opts.update_flags.add(UpdateFlag::Upsert);
+
+                coll.update(&filter, &doc, Some(&opts))
+                    .map(|_| StorageStatus::Available)
+                    .map_err(|_| StoreError::StorageWriteError)
+            })
+    }
+
+    fn unquarantine(&self, id: &str, obj_type: &str) -> StoreResult<StorageStatus> {
+        let filter = doc! {
+            "_id" => id,
+            "type" => obj_type
+        };
+
+        let client = self.pool.pop();
+        let coll = client.get_collection(self.config.name.as_str(), QUARANTINE_COLLECTION);
+
+        let mut opts = RemoveOptions::default();
+        opts.remove_flags.add(RemoveFlag::SingleRemove);
+
+        coll.remove(&filter, Some(&opts))
+            .map(|_| StorageStatus::Removed)
+            .map_err(|_| StoreError::StorageWriteError)
+    }
+
+    fn quarantined(&self) -> StoreResult<Vec<Tombstone>> {
+        let query = doc! {
+            "$query" => {},
+            "$orderby" => {
+                "type" => 1,
+                "_id" => 1
+            }
+        };
+
+        let client = self.pool.pop();
+        let coll = client.get_collection(self.config.name.as_str(), QUARANTINE_COLLECTION);
+
+        coll.find(&query, None)
+            .map_err(|err| {
+                error!(
+                    "Failed to read the quarantine from the Mongo store due to {}",
+                    err
+                );
+                StoreError::StorageFindError
+            })
+            .and_then(|cursor| {
+                cursor
+                    .map(|res| {
+                        res.map_err(|_| StoreError::StorageFindError)
+                            .and_then(|doc| {
+                                bson::from_bson(Bson::Document(doc)).map_err(|err| {
+                                    StoreError::InvalidItemError(IngestError::Deserialize(err))
+                                })
+                            })
+                    })
+                    .collect()
+            })
+    }
+
+    // Connections are only made when the pool is first used, so a write shows that the server is
+    // reachable and that the user may write to the database
+    fn check(&self) -> StoreResult<StorageStatus> {
+        let filter = doc! {
+            "_id" => "preflight"
+        };
+
+        let doc = doc! {
+            "_id" => "preflight",
+            "checked_at" => Utc::now().timestamp()
+        };
+
+        let client = self.pool.pop();
+        let coll = client.get_collection(self.config.name.as_str(), PREFLIGHT_COLLECTION);
+
+        let mut opts = UpdateOptions::default();
+        opts.update_flags.add(UpdateFlag::Upsert);
+
+        coll.update(&filter, &doc, Some(&opts))
+            .map(|_| StorageStatus::Available)
+            .map_err(|err| {
+                error!("Failed to write to the Mongo store due to {}", err);
+                StoreError::StorageWriteError
+            })
+    }
 }
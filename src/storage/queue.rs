@@ -0,0 +1,15 @@
+// Queues of hook deliveries that are kept in storage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Queue {
+    Outbox,
+    DeadLetter,
+}
+
+impl Queue {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Queue::Outbox => "outbox",
+            Queue::DeadLetter => "dead_letter",
+        }
+    }
+}
@@ -1,36 +1,154 @@
+use std::sync::{Arc, Mutex};
+
 use config::DBConfig;
+use hooks::OutboxEntry;
 use objects::Object;
+use storage::checkpoint::Checkpoint;
 use storage::error::{StoreError, StoreResult};
+use storage::queue::Queue;
 use storage::storage::{Storage, StorageStatus};
+use storage::tombstone::Tombstone;
 
 #[derive(Debug)]
 pub struct SinkStore {
     resp: Option<Object>,
+    writable: bool,
+    unreadable: bool,
+    pub removed: Arc<Mutex<Vec<Tombstone>>>,
+    pub checkpoint: Arc<Mutex<Option<Checkpoint>>>,
+    pub queues: Arc<Mutex<Vec<(Queue, OutboxEntry)>>>,
+    pub quarantine: Arc<Mutex<Vec<Tombstone>>>,
 }
 
 impl SinkStore {
     pub fn new(_: Option<&DBConfig>) -> StoreResult<SinkStore> {
-        Ok(SinkStore { resp: None })
+        Ok(SinkStore {
+            resp: None,
+            writable: false,
+            unreadable: false,
+            removed: Arc::new(Mutex::new(vec![])),
+            checkpoint: Arc::new(Mutex::new(None)),
+            queues: Arc::new(Mutex::new(vec![])),
+            quarantine: Arc::new(Mutex::new(vec![])),
+        })
     }
 
     pub fn set_response(&mut self, obj: Object) {
         self.resp = Some(obj);
     }
+
+    // Accepts writes even when there is no cached object to respond with
+    pub fn set_writable(&mut self) {
+        self.writable = true;
+    }
+
+    // Fails every read of a cached object
+    pub fn set_unreadable(&mut self) {
+        self.unreadable = true;
+    }
+
+    pub fn get_removed(&self) -> Vec<Tombstone> {
+        self.removed.lock().unwrap().clone()
+    }
 }
 
 impl Storage<Object> for SinkStore {
     fn get(&self, _: &str, _: &str) -> Option<StoreResult<Object>> {
+        if self.unreadable {
+            return Some(Err(StoreError::StorageFindError));
+        }
+
         self.resp.clone().map(|resp| Ok(resp))
     }
 
     fn put(&self, _: &Object) -> StoreResult<StorageStatus> {
+        if self.writable {
+            return Ok(StorageStatus::Available);
+        }
+
         self.resp
             .clone()
             .ok_or(StoreError::StorageWriteError)
             .and(Ok(StorageStatus::Available))
     }
 
+    fn remove(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus> {
+        let mut removed = self.removed.lock().unwrap();
+        removed.push(tombstone.clone());
+        Ok(StorageStatus::Removed)
+    }
+
+    fn tombstone(&self, id: &str, item_type: &str) -> Option<StoreResult<Tombstone>> {
+        self.get_removed()
+            .into_iter()
+            .rev()
+            .find(|tombstone| tombstone.id == id && tombstone.object_type == item_type)
+            .map(Ok)
+    }
+
+    fn children(&self, _: &str, _: &str) -> StoreResult<Vec<Object>> {
+        Ok(vec![])
+    }
+
     fn updated_at(&self) -> Option<i64> {
         None
     }
+
+    fn checkpoint(&self) -> Option<StoreResult<Checkpoint>> {
+        self.checkpoint.lock().unwrap().clone().map(Ok)
+    }
+
+    fn set_checkpoint(&self, checkpoint: &Checkpoint) -> StoreResult<StorageStatus> {
+        *self.checkpoint.lock().unwrap() = Some(checkpoint.clone());
+        Ok(StorageStatus::Available)
+    }
+
+    fn enqueue(&self, queue: Queue, entry: &OutboxEntry) -> StoreResult<StorageStatus> {
+        let mut queues = self.queues.lock().unwrap();
+        queues.retain(|(q, queued)| !(*q == queue && queued.id == entry.id));
+        queues.push((queue, entry.clone()));
+        Ok(StorageStatus::Available)
+    }
+
+    fn dequeue(&self, queue: Queue, id: &str) -> StoreResult<StorageStatus> {
+        let mut queues = self.queues.lock().unwrap();
+        queues.retain(|(q, queued)| !(*q == queue && queued.id == id));
+        Ok(StorageStatus::Removed)
+    }
+
+    fn queued(&self, queue: Queue) -> StoreResult<Vec<OutboxEntry>> {
+        let mut entries: Vec<OutboxEntry> = self.queues
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(q, _)| *q == queue)
+            .map(|(_, entry)| entry.clone())
+            .collect();
+
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(entries)
+    }
+
+    fn quarantine(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus> {
+        let mut quarantine = self.quarantine.lock().unwrap();
+        quarantine.retain(|held| {
+            !(held.id == tombstone.id && held.object_type == tombstone.object_type)
+        });
+        quarantine.push(tombstone.clone());
+        Ok(StorageStatus::Available)
+    }
+
+    fn unquarantine(&self, id: &str, item_type: &str) -> StoreResult<StorageStatus> {
+        let mut quarantine = self.quarantine.lock().unwrap();
+        quarantine.retain(|held| !(held.id == id && held.object_type == item_type));
+        Ok(StorageStatus::Removed)
+    }
+
+    fn quarantined(&self) -> StoreResult<Vec<Tombstone>> {
+        Ok(self.quarantine.lock().unwrap().clone())
+    }
+
+    fn check(&self) -> StoreResult<StorageStatus> {
+        Ok(StorageStatus::Available)
+    }
 }
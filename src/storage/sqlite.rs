@@ -0,0 +1,823 @@
+extern crate rusqlite;
+extern crate serde_json;
+
+use self::rusqlite::{Connection, OptionalExtension, Row, NO_PARAMS};
+use self::serde_json::Value as Json;
+
+use std::sync::{Mutex, MutexGuard};
+
+use config::DBConfig;
+use hooks::OutboxEntry;
+use objects::Object;
+use storage::checkpoint::Checkpoint;
+use storage::error::{StoreError, StoreResult};
+use storage::queue::Queue;
+use storage::storage::{Storage, StorageStatus};
+use storage::tombstone::{RemovalReason, Tombstone};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS objects (
+        id TEXT NOT NULL,
+        type TEXT NOT NULL,
+        attributes TEXT NOT NULL,
+        self_url TEXT NOT NULL,
+        updated_at INTEGER,
+        PRIMARY KEY (id, type)
+    );
+    CREATE INDEX IF NOT EXISTS objects_updated_at ON objects (updated_at);
+    CREATE TABLE IF NOT EXISTS tombstones (
+        id TEXT NOT NULL,
+        type TEXT NOT NULL,
+        removed_at INTEGER NOT NULL,
+        reason TEXT NOT NULL,
+        changelog TEXT,
+        PRIMARY KEY (id, type)
+    );
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        name TEXT NOT NULL PRIMARY KEY,
+        run_id TEXT NOT NULL,
+        since INTEGER NOT NULL,
+        started_at INTEGER NOT NULL,
+        completed_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS queue (
+        queue TEXT NOT NULL,
+        id TEXT NOT NULL,
+        entry TEXT NOT NULL,
+        PRIMARY KEY (queue, id)
+    );
+    CREATE TABLE IF NOT EXISTS quarantine (
+        id TEXT NOT NULL,
+        type TEXT NOT NULL,
+        tombstone TEXT NOT NULL,
+        PRIMARY KEY (id, type)
+    );
+";
+
+const UPDATE_CHECKPOINT_NAME: &str = "update";
+
+type TombstoneRow = (String, String, i64, String, Option<String>);
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn new(config: &DBConfig) -> StoreResult<SqliteStore> {
+        let path = config.path.clone().ok_or_else(|| {
+            error!("The sqlite storage engine requires a [db] path to be configured");
+            StoreError::ConnectionError("".to_string())
+        })?;
+
+        let conn = Connection::open(path.as_str()).map_err(|err| {
+            error!("Failed to open SQLite database at {} due to {}", path, err);
+            StoreError::ConnectionError(path.clone())
+        })?;
+
+        SqliteStore::from_connection(conn).map_err(|_| StoreError::ConnectionError(path))
+    }
+
+    fn from_connection(conn: Connection) -> StoreResult<SqliteStore> {
+        conn.execute_batch(SCHEMA).map_err(|err| {
+            error!("Failed to create SQLite schema due to {}", err);
+            StoreError::StorageWriteError
+        })?;
+
+        SqliteStore::migrate(&conn).map_err(|err| {
+            error!("Failed to migrate SQLite schema due to {}", err);
+            StoreError::StorageWriteError
+        })?;
+
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    // A panic while the connection was held leaves it usable, as any transaction it was in is
+    // rolled back when dropped
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Adds the columns that databases created by earlier versions are missing
+    fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+        let columns = conn.prepare("PRAGMA table_info(tombstones)")?
+            .query_map(NO_PARAMS, |row| row.get::<_, String>(1))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        if !columns.iter().any(|column| column == "changelog") {
+            conn.execute("ALTER TABLE tombstones ADD COLUMN changelog TEXT", NO_PARAMS)?;
+        }
+
+        Ok(())
+    }
+
+    fn object_from_row(row: &Row) -> rusqlite::Result<(String, String, String, String)> {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    }
+
+    fn tombstone_from_row(row: &Row) -> rusqlite::Result<TombstoneRow> {
+        Ok((
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+        ))
+    }
+}
+
+impl Storage<Object> for SqliteStore {
+    fn get(&self, id: &str, obj_type: &str) -> Option<StoreResult<Object>> {
+        let conn = self.conn();
+
+        conn.query_row(
+            "SELECT id, type, attributes, self_url FROM objects WHERE id = ?1 AND type = ?2",
+            &[id, obj_type],
+            SqliteStore::object_from_row,
+        ).optional()
+            .map_err(|err| {
+                error!("Failed to get {} from the SQLite store due to {}", id, err);
+                StoreError::StorageFindError
+            })
+            .map(|row| {
+                row.map(|(id, obj_type, attributes, self_url)| {
+                    serde_json::from_str::<Json>(attributes.as_str())
+                        .map(|attrs| Object::new(id, attrs, obj_type, self_url))
+                        .map_err(|err| StoreError::InvalidItemError(err.into()))
+                })
+            })
+            .unwrap_or_else(|err| Some(Err(err)))
+    }
+
+    fn put(&self, item: &Object) -> StoreResult<StorageStatus> {
+        let attributes = item.attributes.to_string();
+        let mut conn = self.conn();
+
+        // Written together so that an object is never both live and tombstoned
+        conn.transaction()
+            .and_then(|tx| {
+                tx.execute(
+                    "INSERT OR REPLACE INTO objects (id, type, attributes, self_url, updated_at) \
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![
+                        item.id,
+                        item.object_type,
+                        attributes,
+                        item.self_url,
+                        item.updated_at()
+                    ],
+                )?;
+                tx.execute(
+                    "DELETE FROM tombstones WHERE id = ?1 AND type = ?2",
+                    &[&item.id, &item.object_type],
+                )?;
+                tx.commit()
+            })
+            .map(|_| StorageStatus::Available)
+            .map_err(|err| {
+                error!("Failed to write {} to the SQLite store due to {}", item.id, err);
+                StoreError::StorageWriteError
+            })
+    }
+
+    fn remove(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus> {
+        let reason = match serde_json::to_value(tombstone.reason) {
+            Ok(Json::String(reason)) => reason,
+            _ => return Err(StoreError::StorageWriteError),
+        };
+
+        let changelog = match tombstone.changelog {
+            Some(ref changelog) => match serde_json::to_string(changelog) {
+                Ok(changelog) => Some(changelog),
+                Err(_) => return Err(StoreError::StorageWriteError),
+            },
+            None => None,
+        };
+
+        let mut conn = self.conn();
+
+        conn.transaction()
+            .and_then(|tx| {
+                tx.execute(
+                    "INSERT OR REPLACE INTO tombstones (id, type, removed_at, reason, changelog) \
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![
+                        tombstone.id,
+                        tombstone.object_type,
+                        tombstone.removed_at,
+                        reason,
+                        changelog
+                    ],
+                )?;
+                tx.execute(
+                    "DELETE FROM objects WHERE id = ?1 AND type = ?2",
+                    &[&tombstone.id, &tombstone.object_type],
+                )?;
+                tx.commit()
+            })
+            .map(|_| StorageStatus::Removed)
+            .map_err(|err| {
+                error!(
+                    "Failed to remove {} from the SQLite store due to {}",
+                    tombstone.id, err
+                );
+                StoreError::StorageWriteError
+            })
+    }
+
+    fn tombstone(&self, id: &str, obj_type: &str) -> Option<StoreResult<Tombstone>> {
+        let conn = self.conn();
+
+        conn.query_row(
+            "SELECT id, type, removed_at, reason, changelog FROM tombstones \
+             WHERE id = ?1 AND type = ?2",
+            &[id, obj_type],
+            SqliteStore::tombstone_from_row,
+        ).optional()
+            .map_err(|err| {
+                error!(
+                    "Failed to get tombstone for {} from the SQLite store due to {}",
+                    id, err
+                );
+                StoreError::StorageFindError
+            })
+            .map(|row| {
+                row.map(|(id, obj_type, removed_at, reason, changelog)| {
+                    let changelog = match changelog {
+                        Some(changelog) => Some(serde_json::from_str(changelog.as_str())?),
+                        None => None,
+                    };
+
+                    serde_json::from_value::<RemovalReason>(Json::String(reason))
+                        .map(|reason| Tombstone {
+                            id,
+                            object_type: obj_type,
+                            removed_at,
+                            reason,
+                            changelog,
+                        })
+                })
+                    .map(|res| res.map_err(|err| StoreError::InvalidItemError(err.into())))
+            })
+            .unwrap_or_else(|err| Some(Err(err)))
+    }
+
+    fn children(&self, id: &str, obj_type: &str) -> StoreResult<Vec<Object>> {
+        let conn = self.conn();
+        let mut children = vec![];
+
+        for (child_type, parent_key) in Object::child_types() {
+            let rows = conn.prepare(
+                "SELECT id, type, attributes, self_url FROM objects WHERE type = ?1 \
+                 AND json_extract(attributes, ?2) = ?3 AND json_extract(attributes, ?4) = ?5",
+            ).and_then(|mut stmt| {
+                stmt.query_map(
+                    &[
+                        child_type,
+                        format!("$.{}.id", parent_key).as_str(),
+                        id,
+                        format!("$.{}.type", parent_key).as_str(),
+                        obj_type,
+                    ],
+                    SqliteStore::object_from_row,
+                )?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+                .map_err(|err| {
+                    error!(
+                        "Failed to find children of {} in the SQLite store due to {}",
+                        id, err
+                    );
+                    StoreError::StorageFindError
+                })?;
+
+            for (id, obj_type, attributes, self_url) in rows {
+                let attrs = serde_json::from_str::<Json>(attributes.as_str())
+                    .map_err(|err| StoreError::InvalidItemError(err.into()))?;
+
+                children.push(Object::new(id, attrs, obj_type, self_url));
+            }
+        }
+
+        Ok(children)
+    }
+
+    fn updated_at(&self) -> Option<i64> {
+        let conn = self.conn();
+
+        conn.query_row("SELECT MAX(updated_at) FROM objects", NO_PARAMS, |row| {
+            row.get::<_, Option<i64>>(0)
+        }).ok()
+            .and_then(|max| max)
+    }
+
+    fn checkpoint(&self) -> Option<StoreResult<Checkpoint>> {
+        let conn = self.conn();
+
+        conn.query_row(
+            "SELECT run_id, since, started_at, completed_at FROM checkpoints WHERE name = ?1",
+            &[UPDATE_CHECKPOINT_NAME],
+            |row| {
+                Ok(Checkpoint {
+                    run_id: row.get(0)?,
+                    since: row.get(1)?,
+                    started_at: row.get(2)?,
+                    completed_at: row.get(3)?,
+                })
+            },
+        ).optional()
+            .map_err(|err| {
+                error!("Failed to get checkpoint from the SQLite store due to {}", err);
+                StoreError::StorageFindError
+            })
+            .map(|row| row.map(Ok))
+            .unwrap_or_else(|err| Some(Err(err)))
+    }
+
+    fn set_checkpoint(&self, checkpoint: &Checkpoint) -> StoreResult<StorageStatus> {
+        let conn = self.conn();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO checkpoints (name, run_id, since, started_at, completed_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                UPDATE_CHECKPOINT_NAME,
+                checkpoint.run_id,
+                checkpoint.since,
+                checkpoint.started_at,
+                checkpoint.completed_at
+            ],
+        ).map(|_| StorageStatus::Available)
+            .map_err(|err| {
+                error!("Failed to write checkpoint to the SQLite store due to {}", err);
+                StoreError::StorageWriteError
+            })
+    }
+
+    fn enqueue(&self, queue: Queue, entry: &OutboxEntry) -> StoreResult<StorageStatus> {
+        let serialized = serde_json::to_string(entry)
+            .map_err(|err| StoreError::InvalidItemError(err.into()))?;
+
+        let conn = self.conn();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO queue (queue, id, entry) VALUES (?1, ?2, ?3)",
+            &[queue.name(), entry.id.as_str(), serialized.as_str()],
+        ).map(|_| StorageStatus::Available)
+            .map_err(|err| {
+                error!(
+                    "Failed to write {} to the {} queue in the SQLite store due to {}",
+                    entry.id,
+                    queue.name(),
+                    err
+                );
+                StoreError::StorageWriteError
+            })
+    }
+
+    fn dequeue(&self, queue: Queue, id: &str) -> StoreResult<StorageStatus> {
+        let conn = self.conn();
+
+        conn.execute(
+            "DELETE FROM queue WHERE queue = ?1 AND id = ?2",
+            &[queue.name(), id],
+        ).map(|_| StorageStatus::Removed)
+            .map_err(|err| {
+                error!(
+                    "Failed to remove {} from the {} queue in the SQLite store due to {}",
+                    id,
+                    queue.name(),
+                    err
+                );
+                StoreError::StorageWriteError
+            })
+    }
+
+    fn queued(&self, queue: Queue) -> StoreResult<Vec<OutboxEntry>> {
+        let conn = self.conn();
+
+        let read_error = |err: rusqlite::Error| {
+            error!(
+                "Failed to read the {} queue from the SQLite store due to {}",
+                queue.name(),
+                err
+            );
+            StoreError::StorageFindError
+        };
+
+        let mut stmt = conn.prepare("SELECT entry FROM queue WHERE queue = ?1 ORDER BY id")
+            .map_err(read_error)?;
+
+        let rows = stmt.query_map(&[queue.name()], |row| row.get::<_, String>(0))
+            .map_err(read_error)?;
+
+        rows.map(|row| {
+            row.map_err(read_error).and_then(|entry| {
+                serde_json::from_str::<OutboxEntry>(entry.as_str())
+                    .map_err(|err| StoreError::InvalidItemError(err.into()))
+            })
+        }).collect()
+    }
+
+    fn quarantine(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus> {
+        let serialized = serde_json::to_string(tombstone)
+            .map_err(|err| StoreError::InvalidItemError(err.into()))?;
+
+        let conn = self.conn();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO quarantine (id, type, tombstone) VALUES (?1, ?2, ?3)",
+            &[
+                tombstone.id.as_str(),
+                tombstone.object_type.as_str(),
+                serialized.as_str(),
+            ],
+        ).map(|_| StorageStatus::Available)
+            .map_err(|err| {
+                error!(
+                    "Failed to quarantine {} in the SQLite store due to {}",
+                    tombstone.id, err
+                );
+                StoreError::StorageWriteError
+            })
+    }
+
+    fn unquarantine(&self, id: &str, obj_type: &str) -> StoreResult<StorageStatus> {
+        let conn = self.conn();
+
+        conn.execute(
+            "DELETE FROM quarantine WHERE id = ?1 AND type = ?2",
+            &[id, obj_type],
+        ).map(|_| StorageStatus::Removed)
+            .map_err(|err| {
+                error!(
+                    "Failed to remove {} from the quarantine in the SQLite store due to {}",
+                    id, err
+                );
+                StoreError::StorageWriteError
+            })
+    }
+
+    fn quarantined(&self) -> StoreResult<Vec<Tombstone>> {
+        let conn = self.conn();
+
+        let read_error = |err: rusqlite::Error| {
+            error!(
+                "Failed to read the quarantine from the SQLite store due to {}",
+                err
+            );
+            StoreError::StorageFindError
+        };
+
+        let mut stmt = conn.prepare("SELECT tombstone FROM quarantine ORDER BY type, id")
+            .map_err(read_error)?;
+
+        let rows = stmt.query_map(NO_PARAMS, |row| row.get::<_, String>(0))
+            .map_err(read_error)?;
+
+        rows.map(|row| {
+            row.map_err(read_error).and_then(|tombstone| {
+                serde_json::from_str::<Tombstone>(tombstone.as_str())
+                    .map_err(|err| StoreError::InvalidItemError(err.into()))
+            })
+        }).collect()
+    }
+
+    // Writes a row inside a savepoint that is rolled back, which fails on read only databases
+    fn check(&self) -> StoreResult<StorageStatus> {
+        let conn = self.conn();
+
+        conn.execute_batch("SAVEPOINT preflight").and_then(|_| {
+            let written = conn.execute(
+                "INSERT OR REPLACE INTO checkpoints \
+                 (name, run_id, since, started_at, completed_at) \
+                 VALUES ('preflight', '', 0, 0, 0)",
+                NO_PARAMS,
+            );
+
+            conn.execute_batch("ROLLBACK TO preflight; RELEASE preflight")
+                .and(written)
+        })
+            .map(|_| StorageStatus::Available)
+            .map_err(|err| {
+                error!("Failed to write to the SQLite store due to {}", err);
+                StoreError::StorageWriteError
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::{Connection, OpenFlags};
+    use serde_json::Value as Json;
+
+    use std::{env, fs};
+    use std::panic::{self, AssertUnwindSafe};
+
+    use hooks::{EmitAction, OutboxEntry, Payload};
+    use objects::Object;
+    use storage::{ChangelogEntry, Checkpoint, Queue, RemovalReason, Storage, Tombstone};
+
+    use super::SqliteStore;
+
+    fn memory_store() -> SqliteStore {
+        SqliteStore::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn test_object(id: &str, updated_at: &str) -> Object {
+        Object::new(
+            id.to_string(),
+            json!({
+                "title": "Test Object",
+                "updated_at": updated_at
+            }),
+            "show".to_string(),
+            "http://0.0.0.0/test".to_string(),
+        )
+    }
+
+    #[test]
+    fn stores_and_gets_objects() {
+        let store = memory_store();
+        let obj = test_object("test-id", "2017-01-01T00:00:00Z");
+
+        store.put(&obj).unwrap();
+
+        assert_eq!(store.get("test-id", "show").unwrap().unwrap(), obj);
+        assert!(store.get("test-id", "asset").is_none());
+        assert!(store.get("missing-id", "show").is_none());
+    }
+
+    #[test]
+    fn finds_children_by_parent_reference() {
+        let store = memory_store();
+
+        let child = |id: &str, obj_type: &str, key: &str, parent_id: &str, parent_type: &str| {
+            Object::new(
+                id.to_string(),
+                json!({
+                    key: {
+                        "id": parent_id,
+                        "type": parent_type,
+                        "attributes": {},
+                        "links": { "self": "http://0.0.0.0/parent" }
+                    }
+                }),
+                obj_type.to_string(),
+                "http://0.0.0.0/child".to_string(),
+            )
+        };
+
+        let season = child("test-season", "season", "show", "test-show", "show");
+        let special = child("test-special", "special", "show", "test-show", "show");
+        let asset = child("test-asset", "asset", "parent_tree", "test-show", "show");
+
+        store.put(&test_object("test-show", "2017-01-01T00:00:00Z")).unwrap();
+        store.put(&season).unwrap();
+        store.put(&special).unwrap();
+        store.put(&asset).unwrap();
+        store
+            .put(&child("other-asset", "asset", "parent_tree", "test-show", "episode"))
+            .unwrap();
+        store
+            .put(&child("other-season", "season", "show", "other-show", "show"))
+            .unwrap();
+
+        assert_eq!(
+            store.children("test-show", "show").unwrap(),
+            vec![asset, season, special]
+        );
+        assert!(store.children("test-season", "season").unwrap().is_empty());
+    }
+
+    #[test]
+    fn put_replaces_existing_object() {
+        let store = memory_store();
+
+        store
+            .put(&test_object("test-id", "2017-01-01T00:00:00Z"))
+            .unwrap();
+
+        let updated = test_object("test-id", "2017-02-01T00:00:00Z");
+        store.put(&updated).unwrap();
+
+        assert_eq!(store.get("test-id", "show").unwrap().unwrap(), updated);
+    }
+
+    #[test]
+    fn reports_newest_updated_at() {
+        let store = memory_store();
+
+        assert_eq!(store.updated_at(), None);
+
+        store
+            .put(&test_object("test-id-1", "2017-01-01T00:00:00Z"))
+            .unwrap();
+        store
+            .put(&test_object("test-id-2", "2017-02-01T00:00:00Z"))
+            .unwrap();
+
+        assert_eq!(store.updated_at(), Some(1485907200));
+    }
+
+    #[test]
+    fn removes_objects_with_tombstone() {
+        let store = memory_store();
+        let obj = test_object("test-id", "2017-01-01T00:00:00Z");
+
+        store.put(&obj).unwrap();
+
+        let tombstone = Tombstone::new(
+            "test-id".to_string(),
+            "show".to_string(),
+            RemovalReason::NotAuthorized,
+        );
+        store.remove(&tombstone).unwrap();
+
+        assert!(store.get("test-id", "show").is_none());
+        assert_eq!(store.tombstone("test-id", "show").unwrap().unwrap(), tombstone);
+        assert_eq!(store.updated_at(), None);
+
+        store.put(&obj).unwrap();
+
+        assert!(store.tombstone("test-id", "show").is_none());
+        assert_eq!(
+            store.get("test-id", "show").unwrap().unwrap().attributes["title"],
+            Json::String("Test Object".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_objects_live_when_the_removal_fails() {
+        let store = memory_store();
+        store.put(&test_object("test-id", "2017-01-01T00:00:00Z")).unwrap();
+
+        store
+            .conn()
+            .execute_batch(
+                "CREATE TRIGGER keep BEFORE DELETE ON objects \
+                 BEGIN SELECT RAISE(ABORT, 'kept'); END;",
+            )
+            .unwrap();
+
+        let tombstone = Tombstone::new(
+            "test-id".to_string(),
+            "show".to_string(),
+            RemovalReason::NotFound,
+        );
+
+        assert!(store.remove(&tombstone).is_err());
+        assert!(store.tombstone("test-id", "show").is_none());
+        assert!(store.get("test-id", "show").is_some());
+    }
+
+    #[test]
+    fn recovers_from_a_poisoned_connection() {
+        let store = memory_store();
+
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _conn = store.conn();
+            panic!("poisoning the connection");
+        }));
+
+        store.put(&test_object("test-id", "2017-01-01T00:00:00Z")).unwrap();
+        assert!(store.get("test-id", "show").is_some());
+    }
+
+    #[test]
+    fn stores_changelog_entries_with_tombstones() {
+        let store = memory_store();
+
+        let tombstone = Tombstone::new(
+            "test-id".to_string(),
+            "show".to_string(),
+            RemovalReason::Changelog,
+        ).with_changelog(Some(ChangelogEntry {
+            action: "delete".to_string(),
+            timestamp: "2017-02-21T20:42:27.010750Z".to_string(),
+        }));
+        store.remove(&tombstone).unwrap();
+
+        assert_eq!(store.tombstone("test-id", "show").unwrap().unwrap(), tombstone);
+    }
+
+    #[test]
+    fn migrates_tombstones_without_changelog() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tombstones (
+                id TEXT NOT NULL,
+                type TEXT NOT NULL,
+                removed_at INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                PRIMARY KEY (id, type)
+            );
+            INSERT INTO tombstones VALUES ('test-id', 'show', 1485907200, 'not_found');",
+        ).unwrap();
+
+        let store = SqliteStore::from_connection(conn).unwrap();
+        let tombstone = store.tombstone("test-id", "show").unwrap().unwrap();
+
+        assert_eq!(tombstone.reason, RemovalReason::NotFound);
+        assert_eq!(tombstone.changelog, None);
+    }
+
+    #[test]
+    fn holds_tombstones_in_quarantine() {
+        let store = memory_store();
+
+        let show = Tombstone::new(
+            "test-id".to_string(),
+            "show".to_string(),
+            RemovalReason::NotAuthorized,
+        );
+        let asset = Tombstone::new(
+            "test-id".to_string(),
+            "asset".to_string(),
+            RemovalReason::NotFound,
+        );
+        store.quarantine(&show).unwrap();
+        store.quarantine(&asset).unwrap();
+        store.quarantine(&show).unwrap();
+
+        assert_eq!(store.quarantined().unwrap(), vec![asset.clone(), show]);
+        assert!(store.tombstone("test-id", "show").is_none());
+
+        store.unquarantine("test-id", "show").unwrap();
+
+        assert_eq!(store.quarantined().unwrap(), vec![asset]);
+    }
+
+    #[test]
+    fn checks_that_the_database_is_writable() {
+        let path = env::temp_dir().join("mm_api_notify_check.db");
+        let _ = fs::remove_file(&path);
+
+        let store = SqliteStore::from_connection(Connection::open(&path).unwrap()).unwrap();
+        assert!(store.check().is_ok());
+        assert!(store.checkpoint().is_none());
+
+        let read_only = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY);
+        let store = SqliteStore::from_connection(read_only.unwrap()).unwrap();
+        assert!(store.check().is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaces_update_checkpoint() {
+        let store = memory_store();
+
+        assert!(store.checkpoint().is_none());
+
+        let first = Checkpoint::new(1485907200, 1485907260);
+        store.set_checkpoint(&first).unwrap();
+
+        assert_eq!(store.checkpoint().unwrap().unwrap(), first);
+
+        let second = Checkpoint::new(1485907260, 1485907320);
+        store.set_checkpoint(&second).unwrap();
+
+        assert_eq!(store.checkpoint().unwrap().unwrap(), second);
+    }
+
+    #[test]
+    fn keeps_queues_in_order() {
+        let store = memory_store();
+        let payload = Payload::new(
+            json!({"id": "test-id", "type": "show"})
+                .as_object()
+                .unwrap()
+                .to_owned(),
+        );
+
+        let first = OutboxEntry::new(
+            "http://0.0.0.0/hook/".to_string(),
+            EmitAction::Update,
+            payload.clone(),
+        );
+        let second = OutboxEntry::new(
+            "http://0.0.0.0/hook/".to_string(),
+            EmitAction::Delete,
+            payload,
+        );
+
+        store.enqueue(Queue::Outbox, &second).unwrap();
+        store.enqueue(Queue::Outbox, &first).unwrap();
+
+        assert_eq!(
+            store.queued(Queue::Outbox).unwrap(),
+            vec![first.clone(), second.clone()]
+        );
+        assert!(store.queued(Queue::DeadLetter).unwrap().is_empty());
+
+        store.dequeue(Queue::Outbox, first.id.as_str()).unwrap();
+        store.enqueue(Queue::DeadLetter, &first).unwrap();
+
+        assert_eq!(store.queued(Queue::Outbox).unwrap(), vec![second]);
+        assert_eq!(store.queued(Queue::DeadLetter).unwrap(), vec![first]);
+    }
+}
@@ -1,11 +1,90 @@
+use hooks::OutboxEntry;
+use storage::checkpoint::Checkpoint;
 use storage::error::StoreResult;
+use storage::queue::Queue;
+use storage::tombstone::Tombstone;
 
 pub enum StorageStatus {
     Available,
+    Removed,
 }
 
 pub trait Storage<T> {
     fn get(&self, id: &str, item_type: &str) -> Option<StoreResult<T>>;
     fn put(&self, item: &T) -> StoreResult<StorageStatus>;
+    fn remove(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus>;
+    fn tombstone(&self, id: &str, item_type: &str) -> Option<StoreResult<Tombstone>>;
+    fn children(&self, id: &str, item_type: &str) -> StoreResult<Vec<T>>;
     fn updated_at(&self) -> Option<i64>;
+    fn checkpoint(&self) -> Option<StoreResult<Checkpoint>>;
+    fn set_checkpoint(&self, checkpoint: &Checkpoint) -> StoreResult<StorageStatus>;
+    fn enqueue(&self, queue: Queue, entry: &OutboxEntry) -> StoreResult<StorageStatus>;
+    fn dequeue(&self, queue: Queue, id: &str) -> StoreResult<StorageStatus>;
+    fn queued(&self, queue: Queue) -> StoreResult<Vec<OutboxEntry>>;
+    fn quarantine(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus>;
+    fn unquarantine(&self, id: &str, item_type: &str) -> StoreResult<StorageStatus>;
+    fn quarantined(&self) -> StoreResult<Vec<Tombstone>>;
+    fn check(&self) -> StoreResult<StorageStatus>;
+}
+
+impl<T, S: Storage<T> + ?Sized> Storage<T> for Box<S> {
+    fn get(&self, id: &str, item_type: &str) -> Option<StoreResult<T>> {
+        (**self).get(id, item_type)
+    }
+
+    fn put(&self, item: &T) -> StoreResult<StorageStatus> {
+        (**self).put(item)
+    }
+
+    fn remove(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus> {
+        (**self).remove(tombstone)
+    }
+
+    fn tombstone(&self, id: &str, item_type: &str) -> Option<StoreResult<Tombstone>> {
+        (**self).tombstone(id, item_type)
+    }
+
+    fn children(&self, id: &str, item_type: &str) -> StoreResult<Vec<T>> {
+        (**self).children(id, item_type)
+    }
+
+    fn updated_at(&self) -> Option<i64> {
+        (**self).updated_at()
+    }
+
+    fn checkpoint(&self) -> Option<StoreResult<Checkpoint>> {
+        (**self).checkpoint()
+    }
+
+    fn set_checkpoint(&self, checkpoint: &Checkpoint) -> StoreResult<StorageStatus> {
+        (**self).set_checkpoint(checkpoint)
+    }
+
+    fn enqueue(&self, queue: Queue, entry: &OutboxEntry) -> StoreResult<StorageStatus> {
+        (**self).enqueue(queue, entry)
+    }
+
+    fn dequeue(&self, queue: Queue, id: &str) -> StoreResult<StorageStatus> {
+        (**self).dequeue(queue, id)
+    }
+
+    fn queued(&self, queue: Queue) -> StoreResult<Vec<OutboxEntry>> {
+        (**self).queued(queue)
+    }
+
+    fn quarantine(&self, tombstone: &Tombstone) -> StoreResult<StorageStatus> {
+        (**self).quarantine(tombstone)
+    }
+
+    fn unquarantine(&self, id: &str, item_type: &str) -> StoreResult<StorageStatus> {
+        (**self).unquarantine(id, item_type)
+    }
+
+    fn quarantined(&self) -> StoreResult<Vec<Tombstone>> {
+        (**self).quarantined()
+    }
+
+    fn check(&self) -> StoreResult<StorageStatus> {
+        (**self).check()
+    }
 }
@@ -0,0 +1,63 @@
+extern crate chrono;
+
+use self::chrono::offset::Utc;
+
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalReason {
+    Changelog,
+    NotFound,
+    NotAuthorized,
+    Parent,
+}
+
+impl fmt::Display for RemovalReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RemovalReason::Changelog => write!(f, "it was listed as deleted in the changelog"),
+            RemovalReason::NotFound => write!(f, "the API no longer returns it (404)"),
+            RemovalReason::NotAuthorized => {
+                write!(f, "the API key is no longer authorized to access it (403)")
+            }
+            RemovalReason::Parent => write!(f, "an object it belongs to was removed"),
+        }
+    }
+}
+
+// The action and timestamp of the changelog entry that led to an object being removed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChangelogEntry {
+    pub action: String,
+    pub timestamp: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Tombstone {
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub removed_at: i64,
+    pub reason: RemovalReason,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<ChangelogEntry>,
+}
+
+impl Tombstone {
+    pub fn new(id: String, object_type: String, reason: RemovalReason) -> Tombstone {
+        Tombstone {
+            id,
+            object_type,
+            removed_at: Utc::now().timestamp(),
+            reason,
+            changelog: None,
+        }
+    }
+
+    pub fn with_changelog(mut self, changelog: Option<ChangelogEntry>) -> Tombstone {
+        self.changelog = changelog;
+        self
+    }
+}
@@ -3,11 +3,75 @@ extern crate mm_client;
 
 use chrono::Duration;
 
+use std::ops::Add;
+
 use client::APIClient;
 use objects::Object;
 use storage::Storage;
 
-pub type ImportResult = (i64, i64);
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImportResult {
+    pub success: i64,
+    pub failure: i64,
+    pub skipped: i64,
+    pub unchanged: i64,
+    pub delivered: i64,
+    pub undelivered: i64,
+}
+
+impl ImportResult {
+    pub fn new(success: i64, failure: i64) -> ImportResult {
+        ImportResult {
+            success,
+            failure,
+            ..ImportResult::default()
+        }
+    }
+
+    pub fn skipped() -> ImportResult {
+        ImportResult {
+            skipped: 1,
+            ..ImportResult::default()
+        }
+    }
+
+    pub fn unchanged() -> ImportResult {
+        ImportResult {
+            unchanged: 1,
+            ..ImportResult::default()
+        }
+    }
+
+    pub fn deliveries(delivered: i64, undelivered: i64) -> ImportResult {
+        ImportResult {
+            delivered,
+            undelivered,
+            ..ImportResult::default()
+        }
+    }
+}
+
+impl Add for ImportResult {
+    type Output = ImportResult;
+
+    fn add(self, other: ImportResult) -> ImportResult {
+        ImportResult {
+            success: self.success + other.success,
+            failure: self.failure + other.failure,
+            skipped: self.skipped + other.skipped,
+            unchanged: self.unchanged + other.unchanged,
+            delivered: self.delivered + other.delivered,
+            undelivered: self.undelivered + other.undelivered,
+        }
+    }
+}
+
+impl From<(i64, i64)> for ImportResult {
+    fn from((success, failure): (i64, i64)) -> ImportResult {
+        ImportResult::new(success, failure)
+    }
+}
+
 pub type RunResult = (Duration, ImportResult);
 
 pub trait ThreadedAPI: APIClient + Sync {}